@@ -0,0 +1,80 @@
+#[cfg(not(feature = "library"))]
+use cosmwasm_std::entry_point;
+use cosmwasm_std::{to_json_binary, DepsMut, Env, MessageInfo, Reply, Response, StdError, WasmMsg};
+use cw2::set_contract_version;
+use cw_utils::parse_reply_instantiate_data;
+
+use amalgam_utils::tokenfactory::{self, TFToken};
+
+use crate::msg::InstantiateMsg;
+use crate::state::{instances, Config, Instance, CONFIG, PENDING_INSTANCE};
+use crate::ContractResult;
+
+// version info for migration info
+const CONTRACT_NAME: &str = env!("CARGO_PKG_NAME");
+const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+pub const INSTANTIATE_REPLY_ID: u64 = 1;
+
+/// Id of the Amalgam created in each instance, which is always the first, so instances answer the voting module
+/// queries without an Amalgam id.
+pub const INSTANCE_AMALGAM: u64 = 1;
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn instantiate(
+  deps: DepsMut,
+  _env: Env,
+  info: MessageInfo,
+  msg: InstantiateMsg,
+) -> ContractResult<Response> {
+  set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+  let owner = match msg.owner {
+    Some(owner) => deps.api.addr_validate(&owner)?,
+    None => info.sender,
+  };
+  CONFIG.save(deps.storage, &Config {
+    owner: owner.clone(),
+    amalgam_code_id: msg.amalgam_code_id,
+  })?;
+
+  Ok(Response::new()
+    .add_attribute("method", "instantiate")
+    .add_attribute("owner", owner)
+  )
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> ContractResult<Response> {
+  match msg.id {
+    INSTANTIATE_REPLY_ID => {
+      let res = parse_reply_instantiate_data(msg)?;
+      let address = deps.api.addr_validate(&res.contract_address)?;
+
+      let pending = PENDING_INSTANCE.load(deps.storage)?;
+      PENDING_INSTANCE.remove(deps.storage);
+
+      // the instance verifies tokenfactory reports this denom when creating it
+      let denom = tokenfactory::osmosis::TFToken::new(address.clone(), pending.subdenom).denom();
+      instances().save(deps.storage, &address, &Instance {
+        address: address.clone(),
+        amalgam: INSTANCE_AMALGAM,
+        denom: denom.clone(),
+        admin: pending.admin,
+        creator: pending.creator,
+      })?;
+
+      Ok(Response::new()
+        .add_attribute("action", "register_instance")
+        .add_attribute("instance", &address)
+        .add_attribute("denom", denom)
+        .add_message(WasmMsg::Execute {
+          contract_addr: address.to_string(),
+          msg: to_json_binary(&pending.msg)?,
+          funds: pending.fee,
+        })
+      )
+    }
+    id => Err(StdError::generic_err(format!("Unknown reply id: {}", id)).into()),
+  }
+}
@@ -0,0 +1,21 @@
+use cosmwasm_std::StdError;
+use cw_utils::ParseReplyError;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ContractError {
+  #[error("{0}")]
+  Std(#[from] StdError),
+
+  #[error("{0}")]
+  ParseReply(#[from] ParseReplyError),
+
+  #[error("Unauthorized")]
+  Unauthorized,
+
+  #[error("Funds do not cover the denom creation fee")]
+  InsufficientCreationFee,
+
+  #[error("Unknown instance")]
+  UnknownInstance,
+}
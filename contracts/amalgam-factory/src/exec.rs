@@ -0,0 +1,447 @@
+#[cfg(not(feature = "library"))]
+use cosmwasm_std::entry_point;
+use cosmwasm_std::{
+  to_json_binary, BankMsg, DepsMut, Empty, Env, MessageInfo, Order, Response, StdResult, SubMsg, WasmMsg,
+};
+use cw_storage_plus::Bound;
+
+use amalgam::msg::{AmalgamResponse, ComponentsResponse, MigrateMsg, QueryMsg as AmalgamQueryMsg};
+use amalgam_utils::tokenfactory::{self, osmosis::query_params};
+
+use crate::contract::INSTANTIATE_REPLY_ID;
+use crate::msg::ExecuteMsg;
+use crate::state::{instances, PendingInstance, COMPONENT_INSTANCES, CONFIG, PENDING_INSTANCE};
+use crate::{ContractError, ContractResult};
+
+const DEFAULT_MIGRATION_LIMIT: u32 = 10;
+const MAX_MIGRATION_LIMIT: u32 = 30;
+
+struct ExecuteContext<'a> {
+  deps: DepsMut<'a>,
+  env: Env,
+  info: MessageInfo,
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn execute(
+  deps: DepsMut,
+  env: Env,
+  info: MessageInfo,
+  msg: ExecuteMsg,
+) -> ContractResult<Response> {
+  let mut ctx = ExecuteContext { deps, env, info };
+
+  match msg {
+    ExecuteMsg::CreateAmalgam { subdenom, metadata, admin, fee_recipient, unstaking_duration, track_history, vote_escrow } => {
+      let msg = amalgam::msg::ExecuteMsg::CreateAmalgam {
+        subdenom: subdenom.clone(),
+        metadata,
+        admin: admin.clone(),
+        dao: Some(ctx.info.sender.to_string()),
+        fee_recipient,
+        unstaking_duration,
+        track_history,
+        vote_escrow,
+      };
+      create_amalgam(&mut ctx, subdenom, admin, msg)
+    }
+    ExecuteMsg::SyncInstance { address } =>
+      sync_instance(&mut ctx, address),
+    ExecuteMsg::UpdateCodeId { code_id } =>
+      update_code_id(&mut ctx, code_id),
+    ExecuteMsg::MigrateInstances { start_after, limit } =>
+      migrate_instances(&mut ctx, start_after, limit),
+    ExecuteMsg::UpdateOwner { owner } =>
+      update_owner(&mut ctx, owner),
+  }
+}
+
+fn create_amalgam(ctx: &mut ExecuteContext, subdenom: String, admin: String, msg: amalgam::msg::ExecuteMsg) -> ContractResult<Response> {
+  let config = CONFIG.load(ctx.deps.storage)?;
+  let admin = ctx.deps.api.addr_validate(&admin)?;
+
+  // the instance pays the creation fee, so it is forwarded along with the `CreateAmalgam` message in `reply`
+  let fee = query_params(&ctx.deps.querier)?.creation_fee()?;
+  let excess = tokenfactory::deduct_fee(ctx.info.funds.clone(), &fee)
+    .ok_or(ContractError::InsufficientCreationFee)?;
+
+  PENDING_INSTANCE.save(ctx.deps.storage, &PendingInstance {
+    creator: ctx.info.sender.clone(),
+    admin: admin.to_string(),
+    subdenom: subdenom.clone(),
+    fee,
+    msg,
+  })?;
+
+  // the factory stays the wasm admin of its instances to migrate them
+  let instantiate = WasmMsg::Instantiate {
+    admin: Some(ctx.env.contract.address.to_string()),
+    code_id: config.amalgam_code_id,
+    msg: to_json_binary(&amalgam::msg::InstantiateMsg { factory: Some(ctx.env.contract.address.to_string()) })?,
+    funds: vec![],
+    label: format!("amalgam {}", subdenom),
+  };
+
+  let mut res = Response::new()
+    .add_attribute("action", "create_amalgam")
+    .add_attribute("creator", ctx.info.sender.to_string())
+    .add_submessage(SubMsg::reply_on_success(instantiate, INSTANTIATE_REPLY_ID));
+
+  if !excess.is_empty() {
+    res = res.add_message(BankMsg::Send { to_address: ctx.info.sender.to_string(), amount: excess });
+  }
+
+  Ok(res)
+}
+
+fn sync_instance(ctx: &mut ExecuteContext, address: String) -> ContractResult<Response> {
+  let address = ctx.deps.api.addr_validate(&address)?;
+  let mut instance = instances().may_load(ctx.deps.storage, &address)?.ok_or(ContractError::UnknownInstance)?;
+
+  let amalgam: AmalgamResponse = ctx.deps.querier.query_wasm_smart(&address, &AmalgamQueryMsg::Amalgam {
+    amalgam: instance.amalgam,
+  })?;
+  let components: ComponentsResponse = ctx.deps.querier.query_wasm_smart(&address, &AmalgamQueryMsg::Components {
+    amalgam: instance.amalgam,
+  })?;
+
+  instance.admin = amalgam.state.admin;
+  instances().save(ctx.deps.storage, &address, &instance)?;
+
+  // components can never be removed, so the index only ever grows
+  for component in &components.components {
+    COMPONENT_INSTANCES.save(ctx.deps.storage, (&component.token, &address), &Empty {})?;
+  }
+
+  Ok(Response::new()
+    .add_attribute("action", "sync_instance")
+    .add_attribute("instance", address)
+    .add_attribute("components", components.components.len().to_string())
+  )
+}
+
+fn update_code_id(ctx: &mut ExecuteContext, code_id: u64) -> ContractResult<Response> {
+  let mut config = helpers::assert_owner(ctx)?;
+  config.amalgam_code_id = code_id;
+  CONFIG.save(ctx.deps.storage, &config)?;
+
+  Ok(Response::new()
+    .add_attribute("action", "update_code_id")
+    .add_attribute("code_id", code_id.to_string())
+  )
+}
+
+fn migrate_instances(ctx: &mut ExecuteContext, start_after: Option<String>, limit: Option<u32>) -> ContractResult<Response> {
+  let config = helpers::assert_owner(ctx)?;
+
+  let start_after = start_after
+    .map(|addr| ctx.deps.api.addr_validate(&addr))
+    .transpose()?;
+  let limit = limit.unwrap_or(DEFAULT_MIGRATION_LIMIT).min(MAX_MIGRATION_LIMIT) as usize;
+  let addresses = instances()
+    .keys(ctx.deps.storage, start_after.as_ref().map(Bound::exclusive), None, Order::Ascending)
+    .take(limit)
+    .collect::<StdResult<Vec<_>>>()?;
+
+  let msg = to_json_binary(&MigrateMsg {})?;
+  let mut res = Response::new()
+    .add_attribute("action", "migrate_instances")
+    .add_attribute("code_id", config.amalgam_code_id.to_string())
+    .add_attribute("count", addresses.len().to_string());
+
+  // the last migrated instance is where the next page starts
+  if let Some(last) = addresses.last() {
+    res = res.add_attribute("last", last.to_string());
+  }
+
+  for address in addresses {
+    res = res.add_message(WasmMsg::Migrate {
+      contract_addr: address.to_string(),
+      new_code_id: config.amalgam_code_id,
+      msg: msg.clone(),
+    });
+  }
+
+  Ok(res)
+}
+
+fn update_owner(ctx: &mut ExecuteContext, owner: String) -> ContractResult<Response> {
+  let mut config = helpers::assert_owner(ctx)?;
+  config.owner = ctx.deps.api.addr_validate(&owner)?;
+  CONFIG.save(ctx.deps.storage, &config)?;
+
+  Ok(Response::new()
+    .add_attribute("action", "update_owner")
+    .add_attribute("new_owner", owner)
+  )
+}
+
+mod helpers {
+  use super::*;
+
+  use crate::state::Config;
+
+  pub fn assert_owner(ctx: &ExecuteContext) -> ContractResult<Config> {
+    let config = CONFIG.load(ctx.deps.storage)?;
+    if ctx.info.sender != config.owner {
+      return Err(ContractError::Unauthorized);
+    }
+    Ok(config)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::marker::PhantomData;
+
+  use amalgam::conversion::{ConversionFunction, Ratio};
+  use amalgam::msg::FactoryExecuteMsg;
+  use amalgam::state::{Component, State};
+  use amalgam_utils::asset::{AssetInfo, AssetInfoUnchecked};
+  use amalgam_utils::tokenfactory::osmosis::{Params, QueryParamsResponse};
+  use amalgam_utils::tokenfactory::Coin as ProtoCoin;
+  use cosmwasm_std::testing::{mock_env, mock_info, MockApi, MockQuerier, MockStorage};
+  use cosmwasm_std::{
+    coins, from_json, Addr, ContractResult as QueryResult, CosmosMsg, Deps, OwnedDeps, Querier, QuerierResult,
+    QueryRequest, Reply, SubMsgResponse, SubMsgResult, SystemError, SystemResult, WasmQuery,
+  };
+  use prost::Message;
+
+  use crate::contract::{instantiate, reply};
+  use crate::msg::{InstancesResponse, InstantiateMsg, QueryMsg};
+  use crate::query::query;
+  use crate::state::Instance;
+
+  /// Answers the tokenfactory params query with a creation fee of 100 uosmo.
+  struct TokenfactoryQuerier(MockQuerier);
+
+  impl Querier for TokenfactoryQuerier {
+    fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+      match from_json::<QueryRequest<Empty>>(bin_request) {
+        Ok(QueryRequest::Stargate { path, .. }) if path == "/osmosis.tokenfactory.v1beta1.Query/Params" => {
+          let res = QueryParamsResponse {
+            params: Some(Params {
+              denom_creation_fee: vec![ProtoCoin { denom: "uosmo".to_string(), amount: "100".to_string() }],
+              denom_creation_gas_consume: 0,
+            }),
+          };
+          SystemResult::Ok(QueryResult::Ok(res.encode_to_vec().into()))
+        }
+        Ok(_) => self.0.raw_query(bin_request),
+        Err(err) => SystemResult::Err(SystemError::InvalidRequest {
+          error: err.to_string(),
+          request: to_json_binary(bin_request).unwrap(),
+        }),
+      }
+    }
+  }
+
+  fn setup() -> OwnedDeps<MockStorage, MockApi, TokenfactoryQuerier> {
+    let mut deps = OwnedDeps {
+      storage: MockStorage::default(),
+      api: MockApi::default(),
+      querier: TokenfactoryQuerier(MockQuerier::default()),
+      custom_query_type: PhantomData,
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info("owner", &[]), InstantiateMsg {
+      owner: None,
+      amalgam_code_id: 7,
+    }).unwrap();
+    deps
+  }
+
+  fn create_msg(subdenom: &str) -> ExecuteMsg {
+    ExecuteMsg::CreateAmalgam {
+      subdenom: subdenom.to_string(),
+      metadata: Default::default(),
+      admin: "admin".to_string(),
+      fee_recipient: None,
+      unstaking_duration: None,
+      track_history: None,
+      vote_escrow: None,
+    }
+  }
+
+  /// Reply to the instantiation of `address`, with the protobuf encoded `MsgInstantiateContractResponse`.
+  fn instantiate_reply(address: &str) -> Reply {
+    let mut data = vec![0x0a, address.len() as u8];
+    data.extend_from_slice(address.as_bytes());
+    Reply {
+      id: INSTANTIATE_REPLY_ID,
+      result: SubMsgResult::Ok(SubMsgResponse { events: vec![], data: Some(data.into()) }),
+    }
+  }
+
+  /// Create an instance at `address` through the factory on behalf of `creator`.
+  fn create_instance(deps: &mut OwnedDeps<MockStorage, MockApi, TokenfactoryQuerier>, creator: &str, address: &str) {
+    let info = mock_info(creator, &coins(100, "uosmo"));
+    execute(deps.as_mut(), mock_env(), info, create_msg("lp")).unwrap();
+    reply(deps.as_mut(), mock_env(), instantiate_reply(address)).unwrap();
+  }
+
+  fn query_instances(deps: Deps, msg: QueryMsg) -> Vec<String> {
+    let res: InstancesResponse = from_json(query(deps, mock_env(), msg).unwrap()).unwrap();
+    res.instances.into_iter().map(|instance| instance.address.to_string()).collect()
+  }
+
+  #[test]
+  fn test_create_amalgam() {
+    let mut deps = setup();
+    let env = mock_env();
+
+    let res = execute(deps.as_mut(), env.clone(), mock_info("dao", &coins(99, "uosmo")), create_msg("lp"));
+    assert!(matches!(res, Err(ContractError::InsufficientCreationFee)));
+
+    let res = execute(deps.as_mut(), env.clone(), mock_info("dao", &coins(150, "uosmo")), create_msg("lp")).unwrap();
+    assert_eq!(res.messages[0].id, INSTANTIATE_REPLY_ID);
+    let CosmosMsg::Wasm(WasmMsg::Instantiate { admin, code_id, msg, .. }) = &res.messages[0].msg else {
+      panic!("expected instantiation")
+    };
+    assert_eq!(admin.as_deref(), Some(env.contract.address.as_str()));
+    assert_eq!(*code_id, 7);
+    // the instance notifies the factory of new components
+    let msg: amalgam::msg::InstantiateMsg = from_json(msg).unwrap();
+    assert_eq!(msg.factory.as_deref(), Some(env.contract.address.as_str()));
+    assert_eq!(res.messages[1].msg, CosmosMsg::Bank(BankMsg::Send {
+      to_address: "dao".to_string(),
+      amount: coins(50, "uosmo"),
+    }));
+
+    // the Amalgam is created once the instance exists, with the fee and the creator as its DAO
+    let res = reply(deps.as_mut(), env.clone(), instantiate_reply("instance")).unwrap();
+    let CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, msg, funds }) = &res.messages[0].msg else {
+      panic!("expected CreateAmalgam")
+    };
+    assert_eq!(contract_addr, "instance");
+    assert_eq!(funds, &coins(100, "uosmo"));
+    let amalgam::msg::ExecuteMsg::CreateAmalgam { dao, subdenom, .. } = from_json(msg).unwrap() else {
+      panic!("expected CreateAmalgam")
+    };
+    assert_eq!(dao.as_deref(), Some("dao"));
+    assert_eq!(subdenom, "lp");
+
+    let instance: Instance = from_json(query(deps.as_ref(), env.clone(), QueryMsg::InstanceByDenom {
+      denom: "factory/instance/lp".to_string(),
+    }).unwrap()).unwrap();
+    assert_eq!(instance, Instance {
+      address: Addr::unchecked("instance"),
+      amalgam: 1,
+      denom: "factory/instance/lp".to_string(),
+      admin: "admin".to_string(),
+      creator: Addr::unchecked("dao"),
+    });
+  }
+
+  #[test]
+  fn test_query_by_creator_and_component() {
+    let mut deps = setup();
+    create_instance(&mut deps, "dao", "instance1");
+    create_instance(&mut deps, "other", "instance2");
+    create_instance(&mut deps, "dao", "instance3");
+
+    assert_eq!(query_instances(deps.as_ref(), QueryMsg::InstancesByCreator {
+      creator: "dao".to_string(),
+      start_after: None,
+      limit: None,
+    }), vec!["instance1", "instance3"]);
+    assert_eq!(query_instances(deps.as_ref(), QueryMsg::InstancesByCreator {
+      creator: "dao".to_string(),
+      start_after: Some("instance1".to_string()),
+      limit: None,
+    }), vec!["instance3"]);
+
+    // instance1 and instance2 accept the LP token, instance2 also changed its admin
+    deps.querier.0.update_wasm(|query| {
+      let WasmQuery::Smart { contract_addr, msg } = query else { panic!("unexpected query") };
+      let components = match contract_addr.as_str() {
+        "instance1" | "instance2" => vec![Component {
+          token: AssetInfo::Native("gamm/pool/1".to_string()),
+          conversion: ConversionFunction::Linear(Ratio::one()),
+          withdrawal_tax: 0,
+          deposit_fee: None,
+          decimals: None,
+        }],
+        _ => vec![],
+      };
+      let res = match from_json(msg).unwrap() {
+        AmalgamQueryMsg::Amalgam { amalgam } => to_json_binary(&AmalgamResponse {
+          id: amalgam,
+          state: State {
+            admin: if contract_addr == "instance2" { "new_admin" } else { "admin" }.to_string(),
+            fee_recipient: None,
+            track_history: false,
+            denom: format!("factory/{}/lp", contract_addr),
+          },
+        }),
+        AmalgamQueryMsg::Components { .. } => to_json_binary(&ComponentsResponse { components }),
+        _ => panic!("unexpected query"),
+      };
+      SystemResult::Ok(QueryResult::Ok(res.unwrap()))
+    });
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("anyone", &[]), ExecuteMsg::SyncInstance {
+      address: "unknown".to_string(),
+    });
+    assert!(matches!(res, Err(ContractError::UnknownInstance)));
+
+    // the index is stale until the instances are synced
+    let by_component = |deps: Deps| query_instances(deps, QueryMsg::InstancesByComponent {
+      asset: AssetInfoUnchecked::Native("gamm/pool/1".to_string()),
+      start_after: None,
+      limit: None,
+    });
+    assert!(by_component(deps.as_ref()).is_empty());
+
+    // instance1 notifies the factory when adding the component, the others are synced by anyone
+    let notification = to_json_binary(&FactoryExecuteMsg::SyncInstance { address: "instance1".to_string() }).unwrap();
+    execute(deps.as_mut(), mock_env(), mock_info("instance1", &[]), from_json(notification).unwrap()).unwrap();
+    assert_eq!(by_component(deps.as_ref()), vec!["instance1"]);
+    for address in ["instance2", "instance3"] {
+      execute(deps.as_mut(), mock_env(), mock_info("anyone", &[]), ExecuteMsg::SyncInstance {
+        address: address.to_string(),
+      }).unwrap();
+    }
+
+    assert_eq!(by_component(deps.as_ref()), vec!["instance1", "instance2"]);
+
+    let instance: Instance = from_json(query(deps.as_ref(), mock_env(), QueryMsg::Instance {
+      address: "instance2".to_string(),
+    }).unwrap()).unwrap();
+    assert_eq!(instance.admin, "new_admin");
+  }
+
+  #[test]
+  fn test_migrate_instances() {
+    let mut deps = setup();
+    for address in ["instance1", "instance2", "instance3"] {
+      create_instance(&mut deps, "dao", address);
+    }
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("dao", &[]), ExecuteMsg::UpdateCodeId { code_id: 8 });
+    assert!(matches!(res, Err(ContractError::Unauthorized)));
+    execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), ExecuteMsg::UpdateCodeId { code_id: 8 }).unwrap();
+
+    let migrate = |start_after: Option<&str>| ExecuteMsg::MigrateInstances {
+      start_after: start_after.map(str::to_string),
+      limit: Some(2),
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("dao", &[]), migrate(None));
+    assert!(matches!(res, Err(ContractError::Unauthorized)));
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), migrate(None)).unwrap();
+    assert_eq!(res.messages.len(), 2);
+    assert_eq!(res.messages[0].msg, CosmosMsg::Wasm(WasmMsg::Migrate {
+      contract_addr: "instance1".to_string(),
+      new_code_id: 8,
+      msg: to_json_binary(&MigrateMsg {}).unwrap(),
+    }));
+    assert!(res.attributes.iter().any(|attr| attr.key == "last" && attr.value == "instance2"));
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), migrate(Some("instance2"))).unwrap();
+    assert_eq!(res.messages.len(), 1);
+
+    assert_eq!(query_instances(deps.as_ref(), QueryMsg::Instances { start_after: None, limit: None }).len(), 3);
+  }
+}
@@ -0,0 +1,106 @@
+use amalgam_utils::asset::AssetInfoUnchecked;
+use amalgam::ve::VoteEscrowConfig;
+use amalgam_utils::tokenfactory::DenomMetadata;
+use cosmwasm_schema::{cw_serde, QueryResponses};
+use cw_utils::Duration;
+
+use crate::state::{Config, Instance};
+
+#[cw_serde]
+pub struct InstantiateMsg {
+  /// Owner of the factory. Defaults to the instantiator if unset.
+  pub owner: Option<String>,
+  /// Code id of the `amalgam` contract to instantiate.
+  pub amalgam_code_id: u64,
+}
+
+#[cw_serde]
+#[allow(clippy::large_enum_variant)]
+pub enum ExecuteMsg {
+  /// Instantiate a new `amalgam` instance and create its Amalgam. The denom creation fee must be sent along, any excess
+  /// is refunded. The sender becomes the DAO the Amalgam acts as a voting module for.
+  CreateAmalgam {
+    subdenom: String,
+    /// Metadata of the Amalgam token.
+    metadata: DenomMetadata,
+    /// Admin of the Amalgam. The only one who can add new tokens to the Amalgamation.
+    admin: String,
+    /// Recipient of deposit fees. Defaults to the admin if unset.
+    fee_recipient: Option<String>,
+    /// Duration unstaked Amalgam is locked for before it can be claimed. Unstaked Amalgam is released immediately if
+    /// unset.
+    unstaking_duration: Option<Duration>,
+    /// Whether to record every deposit and withdrawal in an append-only log. Defaults to false.
+    track_history: Option<bool>,
+    /// Vote-escrow configuration. Locking is disabled if unset.
+    vote_escrow: Option<VoteEscrowConfig>,
+  },
+
+  /// Refresh the admin and components of an instance in the registry. Callable by anyone, and sent by instances
+  /// whenever a component is added.
+  SyncInstance {
+    address: String,
+  },
+
+  /// Set the code id new instances are instantiated from. Callable only by the owner.
+  UpdateCodeId {
+    code_id: u64,
+  },
+
+  /// Migrate a page of instances to the current code id. Callable only by the owner.
+  MigrateInstances {
+    start_after: Option<String>,
+    limit: Option<u32>,
+  },
+
+  /// Transfer ownership of the factory. Callable only by the owner.
+  UpdateOwner {
+    owner: String,
+  },
+}
+
+#[cw_serde]
+#[derive(QueryResponses)]
+pub enum QueryMsg {
+  #[returns(Config)]
+  Config {},
+
+  #[returns(Instance)]
+  Instance {
+    address: String,
+  },
+
+  /// Instance issuing `denom`.
+  #[returns(Instance)]
+  InstanceByDenom {
+    denom: String,
+  },
+
+  /// All instances, by ascending address.
+  #[returns(InstancesResponse)]
+  Instances {
+    start_after: Option<String>,
+    limit: Option<u32>,
+  },
+
+  /// Instances created by `creator`, by ascending address.
+  #[returns(InstancesResponse)]
+  InstancesByCreator {
+    creator: String,
+    start_after: Option<String>,
+    limit: Option<u32>,
+  },
+
+  /// Instances accepting `asset` as a component, by ascending address, as of their last `SyncInstance`.
+  #[returns(InstancesResponse)]
+  InstancesByComponent {
+    asset: AssetInfoUnchecked,
+    start_after: Option<String>,
+    limit: Option<u32>,
+  },
+}
+
+#[cw_serde]
+pub struct InstancesResponse {
+  pub instances: Vec<Instance>,
+}
@@ -0,0 +1,81 @@
+#[cfg(not(feature = "library"))]
+use cosmwasm_std::entry_point;
+use cosmwasm_std::{to_json_binary, Addr, Binary, Deps, Env, Order, StdError, StdResult};
+use cw_storage_plus::Bound;
+
+use amalgam_utils::asset::AssetInfoUnchecked;
+
+use crate::msg::{InstancesResponse, QueryMsg};
+use crate::state::{instances, Instance, COMPONENT_INSTANCES, CONFIG};
+
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 30;
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+  match msg {
+    QueryMsg::Config {} => to_json_binary(&CONFIG.load(deps.storage)?),
+    QueryMsg::Instance { address } => to_json_binary(&query_instance(deps, address)?),
+    QueryMsg::InstanceByDenom { denom } => to_json_binary(&query_instance_by_denom(deps, denom)?),
+    QueryMsg::Instances { start_after, limit } => to_json_binary(&query_instances(deps, start_after, limit)?),
+    QueryMsg::InstancesByCreator { creator, start_after, limit } =>
+      to_json_binary(&query_instances_by_creator(deps, creator, start_after, limit)?),
+    QueryMsg::InstancesByComponent { asset, start_after, limit } =>
+      to_json_binary(&query_instances_by_component(deps, asset, start_after, limit)?),
+  }
+}
+
+fn query_instance(deps: Deps, address: String) -> StdResult<Instance> {
+  let address = deps.api.addr_validate(&address)?;
+  instances().load(deps.storage, &address)
+}
+
+fn query_instance_by_denom(deps: Deps, denom: String) -> StdResult<Instance> {
+  instances().idx.denom.item(deps.storage, denom)?
+    .map(|(_, instance)| instance)
+    .ok_or_else(|| StdError::not_found("Instance"))
+}
+
+fn query_instances(deps: Deps, start_after: Option<String>, limit: Option<u32>) -> StdResult<InstancesResponse> {
+  let start_after = validate_start_after(deps, start_after)?;
+  let instances = instances()
+    .range(deps.storage, start_after.as_ref().map(Bound::exclusive), None, Order::Ascending)
+    .take(page_limit(limit))
+    .map(|item| item.map(|(_, instance)| instance))
+    .collect::<StdResult<_>>()?;
+  Ok(InstancesResponse { instances })
+}
+
+fn query_instances_by_creator(deps: Deps, creator: String, start_after: Option<String>, limit: Option<u32>) -> StdResult<InstancesResponse> {
+  let creator = deps.api.addr_validate(&creator)?;
+  let start_after = validate_start_after(deps, start_after)?;
+  let instances = instances()
+    .idx
+    .creator
+    .prefix(creator)
+    .range(deps.storage, start_after.map(Bound::exclusive), None, Order::Ascending)
+    .take(page_limit(limit))
+    .map(|item| item.map(|(_, instance)| instance))
+    .collect::<StdResult<_>>()?;
+  Ok(InstancesResponse { instances })
+}
+
+fn query_instances_by_component(deps: Deps, asset: AssetInfoUnchecked, start_after: Option<String>, limit: Option<u32>) -> StdResult<InstancesResponse> {
+  let asset = asset.check(deps.api)?;
+  let start_after = validate_start_after(deps, start_after)?;
+  let instances = COMPONENT_INSTANCES
+    .prefix(&asset)
+    .keys(deps.storage, start_after.as_ref().map(Bound::exclusive), None, Order::Ascending)
+    .take(page_limit(limit))
+    .map(|address| instances().load(deps.storage, &address?))
+    .collect::<StdResult<_>>()?;
+  Ok(InstancesResponse { instances })
+}
+
+fn validate_start_after(deps: Deps, start_after: Option<String>) -> StdResult<Option<Addr>> {
+  start_after.map(|addr| deps.api.addr_validate(&addr)).transpose()
+}
+
+fn page_limit(limit: Option<u32>) -> usize {
+  limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize
+}
@@ -0,0 +1,65 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{Addr, Coin, Empty};
+use cw_storage_plus::{Index, IndexList, IndexedMap, Item, Map, MultiIndex, UniqueIndex};
+
+use amalgam_utils::asset::AssetInfo;
+
+#[cw_serde]
+pub struct Config {
+  /// Owner of the factory. The only one who can change the code id and migrate instances.
+  pub owner: Addr,
+  /// Code id of the `amalgam` contract new instances are instantiated from, and existing ones migrated to.
+  pub amalgam_code_id: u64,
+}
+
+/// Amalgam instance created by the factory.
+#[cw_serde]
+pub struct Instance {
+  pub address: Addr,
+  /// Id of the Amalgam within the instance.
+  pub amalgam: u64,
+  pub denom: String,
+  /// Admin of the Amalgam, as of the last `SyncInstance`.
+  pub admin: String,
+  /// Sender of `CreateAmalgam`, which is also the DAO the Amalgam acts as a voting module for.
+  pub creator: Addr,
+}
+
+/// Instance awaiting the reply with its address.
+#[cw_serde]
+pub struct PendingInstance {
+  pub creator: Addr,
+  pub admin: String,
+  pub subdenom: String,
+  /// Denom creation fee forwarded to the instance.
+  pub fee: Vec<Coin>,
+  /// `CreateAmalgam` message executed on the instance once instantiated.
+  pub msg: amalgam::msg::ExecuteMsg,
+}
+
+pub struct InstanceIndexes<'a> {
+  pub denom: UniqueIndex<'a, String, Instance, Addr>,
+  pub creator: MultiIndex<'a, Addr, Instance, Addr>,
+}
+
+impl<'a> IndexList<Instance> for InstanceIndexes<'a> {
+  fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Instance>> + '_> {
+    let v: Vec<&dyn Index<Instance>> = vec![&self.denom, &self.creator];
+    Box::new(v.into_iter())
+  }
+}
+
+pub const CONFIG: Item<Config> = Item::new("config");
+pub const PENDING_INSTANCE: Item<PendingInstance> = Item::new("pending_instance");
+
+/// Registry of instances by address, indexed by denom and creator.
+pub fn instances<'a>() -> IndexedMap<'a, &'a Addr, Instance, InstanceIndexes<'a>> {
+  let indexes = InstanceIndexes {
+    denom: UniqueIndex::new(|instance| instance.denom.clone(), "instances__denom"),
+    creator: MultiIndex::new(|_, instance| instance.creator.clone(), "instances", "instances__creator"),
+  };
+  IndexedMap::new("instances", indexes)
+}
+
+/// Map of component assets and the instances accepting them, as of their last `SyncInstance`.
+pub const COMPONENT_INSTANCES: Map<(&AssetInfo, &Addr), Empty> = Map::new("component_instances");
@@ -1,43 +1,437 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
-use cosmwasm_std::{DepsMut, Env, MessageInfo, Response};
+use cosmwasm_std::{Binary, DepsMut, Env, MessageInfo, Reply, Response, StdError, StdResult, Storage, SubMsgResult};
 use cw2::set_contract_version;
 
+use amalgam_utils::ibc_hooks::MsgTransferResponse;
+use amalgam_utils::stargate;
+use amalgam_utils::tokenfactory::osmosis::MsgCreateDenomResponse;
 use amalgam_utils::tokenfactory::{self, TFToken};
 
 use crate::error::ContractError;
-use crate::msg::InstantiateMsg;
-use crate::state::{State, STATE};
+use crate::msg::{InstantiateMsg, MigrateMsg};
+use crate::state::{AMALGAMS, FACTORY, IBC_TRANSFERS, PENDING_AMALGAM, PENDING_TRANSFER};
+use crate::ContractResult;
 
 // version info for migration info
 const CONTRACT_NAME: &str = env!("CARGO_PKG_NAME");
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
+pub const IBC_TRANSFER_REPLY_ID: u64 = 1;
+pub const CREATE_DENOM_REPLY_ID: u64 = 2;
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
   deps: DepsMut,
-  env: Env,
+  _env: Env,
   info: MessageInfo,
   msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
+  // the creation fee is paid with `CreateAmalgam`, funds sent here would be stuck
+  if !info.funds.is_empty() {
+    return Err(ContractError::InstantiateFunds);
+  }
+
   set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+  if let Some(factory) = msg.factory {
+    FACTORY.save(deps.storage, &deps.api.addr_validate(&factory)?)?;
+  }
 
-  STATE.save(deps.storage, &State {
-    admin: info.sender.to_string(),
-  })?;
+  // Amalgams are created with `ExecuteMsg::CreateAmalgam`
+  Ok(Response::new()
+    .add_attribute("method", "instantiate")
+  )
+}
 
-  let tftoken = get_tftoken(&env);
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(mut deps: DepsMut, env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+  set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+  let migrated = legacy::migrate_state(deps.branch(), &env)?;
+  let rekeyed = legacy::migrate_assets(deps, &env)?;
 
   Ok(Response::new()
-    .add_attribute("method", "instantiate")
-    .add_messages(tftoken.create())
-    .add_messages(tftoken.set_metadata(msg.metadata))
+    .add_attribute("method", "migrate")
+    .add_attribute("version", CONTRACT_VERSION)
+    .add_attribute("migrated_state", migrated.to_string())
+    .add_attribute("rekeyed", rekeyed.to_string())
   )
 }
 
-pub fn get_tftoken(env: &Env) -> impl TFToken {
-  tokenfactory::osmosis::TFToken::new(
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractError> {
+  match msg.id {
+    CREATE_DENOM_REPLY_ID => {
+      let data = reply_data(msg.result)?;
+      let res: MsgCreateDenomResponse = stargate::decode(data.as_slice())?;
+
+      let amalgam = PENDING_AMALGAM.load(deps.storage)?;
+      let mut state = AMALGAMS.load(deps.storage, amalgam)?;
+      if res.new_token_denom != state.denom {
+        return Err(ContractError::DenomMismatch {
+          expected: state.denom,
+          actual: res.new_token_denom,
+        });
+      }
+      state.denom = res.new_token_denom;
+      AMALGAMS.save(deps.storage, amalgam, &state)?;
+      PENDING_AMALGAM.remove(deps.storage);
+
+      Ok(Response::new()
+        .add_attribute("action", "create_denom")
+        .add_attribute("amalgam", amalgam.to_string())
+        .add_attribute("denom", state.denom)
+      )
+    }
+    IBC_TRANSFER_REPLY_ID => {
+      let data = reply_data(msg.result)?;
+      let res: MsgTransferResponse = stargate::decode(data.as_slice())?;
+
+      let transfer = PENDING_TRANSFER.load(deps.storage)?;
+      PENDING_TRANSFER.remove(deps.storage);
+      IBC_TRANSFERS.save(deps.storage, (&transfer.channel, res.sequence), &transfer)?;
+
+      Ok(Response::new()
+        .add_attribute("action", "ibc_transfer")
+        .add_attribute("sequence", res.sequence.to_string())
+      )
+    }
+    id => Err(StdError::generic_err(format!("Unknown reply id: {}", id)).into()),
+  }
+}
+
+/// The token of `amalgam`, with the denom reported by tokenfactory on creation.
+pub fn get_tftoken(storage: &dyn Storage, env: &Env, amalgam: u64) -> ContractResult<impl TFToken> {
+  let state = AMALGAMS.may_load(storage, amalgam)?.ok_or(ContractError::UnknownAmalgam)?;
+  Ok(tokenfactory::osmosis::TFToken::from_denom(
     env.contract.address.clone(),
-    "amalgam".to_string(),
-  )
+    state.denom,
+  ))
+}
+
+fn reply_data(result: SubMsgResult) -> StdResult<Binary> {
+  match result {
+    SubMsgResult::Ok(res) => Ok(res.data.unwrap_or_default()),
+    SubMsgResult::Err(err) => Err(StdError::generic_err(err)),
+  }
+}
+
+/// Storage of the first release, a single Amalgam with its assets under `native:{denom}` and `cw20:{contract}` string
+/// keys.
+mod legacy {
+  use cosmwasm_schema::cw_serde;
+  use cosmwasm_std::{Addr, Decimal, DepsMut, Env, Order, StdResult, Uint128};
+  use cw_storage_plus::{Item, Map};
+
+  use amalgam_utils::asset::AssetInfo;
+
+  use crate::conversion::{ConversionFunction, Ratio};
+  use crate::state::{self, StakingConfig, AMALGAMS, AMALGAM_SEQ, MINTED, STAKING, SUPPLY, TAXES};
+
+  /// Id the Amalgam of the first release is migrated to.
+  pub const AMALGAM: u64 = 1;
+
+  #[cw_serde]
+  pub struct State {
+    pub admin: String,
+  }
+
+  #[cw_serde]
+  pub enum Asset {
+    Native(String),
+    Cw20(String),
+  }
+
+  #[cw_serde]
+  pub struct Component {
+    pub token: Asset,
+    pub weight: Decimal,
+    pub withdrawal_tax: u16,
+  }
+
+  pub const STATE: Item<State> = Item::new("state");
+  pub const COMPONENTS: Map<String, Component> = Map::new("components");
+  pub const BALANCES: Map<String, Uint128> = Map::new("balances");
+
+  /// Move the configuration of the first release to Amalgam 1, with the denom it created on instantiation and its
+  /// admin as the DAO. Returns whether there was a configuration to move.
+  pub fn migrate_state(deps: DepsMut, env: &Env) -> StdResult<bool> {
+    let Some(legacy) = STATE.may_load(deps.storage)? else { return Ok(false) };
+    STATE.remove(deps.storage);
+
+    let denom = format!("factory/{}/amalgam", env.contract.address);
+    let supply = deps.querier.query_supply(&denom)?.amount;
+    AMALGAM_SEQ.save(deps.storage, &AMALGAM)?;
+    AMALGAMS.save(deps.storage, AMALGAM, &state::State {
+      admin: legacy.admin.clone(),
+      fee_recipient: None,
+      track_history: false,
+      denom,
+    })?;
+    SUPPLY.save(deps.storage, AMALGAM, &supply, env.block.height)?;
+    STAKING.save(deps.storage, AMALGAM, &StakingConfig {
+      dao: deps.api.addr_validate(&legacy.admin)?,
+      unstaking_duration: None,
+    })?;
+    Ok(true)
+  }
+
+  /// Move the components and balances of the first release to Amalgam 1, keyed by asset. Typed keys never start with
+  /// a legacy prefix, so migrating again moves nothing. Returns the number of moved components.
+  pub fn migrate_assets(deps: DepsMut, env: &Env) -> StdResult<u64> {
+    // values under typed keys do not deserialize as legacy components, so only the keys are ranged
+    let keys = COMPONENTS.keys_raw(deps.storage, None, None, Order::Ascending)
+      .filter_map(|raw| String::from_utf8(raw).ok())
+      .filter(|key| parse_key(key).is_some())
+      .collect::<Vec<_>>();
+
+    let mut count = 0;
+    for key in keys {
+      let Some(asset) = parse_key(&key) else { continue };
+      let component = COMPONENTS.load(deps.storage, key.clone())?;
+      let balance = BALANCES.may_load(deps.storage, key.clone())?.unwrap_or_default();
+      COMPONENTS.remove(deps.storage, key.clone());
+      BALANCES.remove(deps.storage, key);
+
+      let weight = Ratio::from(component.weight);
+      let minted = weight.mul_floor(balance)?;
+      state::COMPONENTS.save(deps.storage, (AMALGAM, &asset), &state::Component {
+        token: asset.clone(),
+        conversion: ConversionFunction::Linear(weight),
+        withdrawal_tax: component.withdrawal_tax,
+        deposit_fee: None,
+        decimals: None,
+      })?;
+      state::BALANCES.save(deps.storage, (AMALGAM, &asset), &balance, env.block.height)?;
+      // deposits minted `amount * weight`
+      MINTED.save(deps.storage, (AMALGAM, &asset), &minted)?;
+
+      // the first release left withdrawal taxes untracked and collected whatever the contract held above the balance
+      let held = asset.query_balance(&deps.querier, &env.contract.address)?;
+      let taxes = held.saturating_sub(balance);
+      if !taxes.is_zero() {
+        TAXES.save(deps.storage, (AMALGAM, &asset), &taxes)?;
+      }
+      count += 1;
+    }
+    Ok(count)
+  }
+
+  fn parse_key(key: &str) -> Option<AssetInfo> {
+    if let Some(denom) = key.strip_prefix("native:") {
+      return Some(AssetInfo::Native(denom.to_string()));
+    }
+    key.strip_prefix("cw20:").map(|contract| AssetInfo::Cw20(Addr::unchecked(contract)))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::marker::PhantomData;
+
+  use amalgam_utils::tokenfactory::osmosis::{Params, QueryParamsResponse};
+  use amalgam_utils::tokenfactory::Coin as ProtoCoin;
+  use cosmwasm_std::testing::{mock_env, mock_info, MockApi, MockQuerier, MockStorage};
+  use cosmwasm_std::{
+    coin, coins, from_json, to_json_binary, BankMsg, CosmosMsg, Empty, OwnedDeps, Querier, QuerierResult, QueryRequest,
+    SubMsgResponse, SystemError, SystemResult,
+  };
+  use prost::Message;
+
+  use crate::exec::execute;
+  use crate::msg::{AmalgamsResponse, ExecuteMsg, QueryMsg};
+  use crate::query::query;
+
+  /// Answers the tokenfactory params query with a creation fee of 100 uosmo.
+  struct TokenfactoryQuerier(MockQuerier);
+
+  impl Querier for TokenfactoryQuerier {
+    fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+      match from_json::<QueryRequest<Empty>>(bin_request) {
+        Ok(QueryRequest::Stargate { path, .. }) if path == "/osmosis.tokenfactory.v1beta1.Query/Params" => {
+          let res = QueryParamsResponse {
+            params: Some(Params {
+              denom_creation_fee: vec![ProtoCoin { denom: "uosmo".to_string(), amount: "100".to_string() }],
+              denom_creation_gas_consume: 0,
+            }),
+          };
+          SystemResult::Ok(cosmwasm_std::ContractResult::Ok(res.encode_to_vec().into()))
+        }
+        Ok(_) => self.0.raw_query(bin_request),
+        Err(err) => SystemResult::Err(SystemError::InvalidRequest {
+          error: err.to_string(),
+          request: to_json_binary(bin_request).unwrap(),
+        }),
+      }
+    }
+  }
+
+  fn mock_dependencies() -> OwnedDeps<MockStorage, MockApi, TokenfactoryQuerier> {
+    OwnedDeps {
+      storage: MockStorage::default(),
+      api: MockApi::default(),
+      querier: TokenfactoryQuerier(MockQuerier::default()),
+      custom_query_type: PhantomData,
+    }
+  }
+
+  fn create_msg(subdenom: &str) -> ExecuteMsg {
+    ExecuteMsg::CreateAmalgam {
+      subdenom: subdenom.to_string(),
+      metadata: Default::default(),
+      admin: "admin".to_string(),
+      dao: None,
+      fee_recipient: None,
+      unstaking_duration: None,
+      track_history: None,
+      vote_escrow: None,
+    }
+  }
+
+  fn create_denom_reply(denom: &str) -> Reply {
+    Reply {
+      id: CREATE_DENOM_REPLY_ID,
+      result: SubMsgResult::Ok(SubMsgResponse {
+        events: vec![],
+        data: Some(MsgCreateDenomResponse { new_token_denom: denom.to_string() }.encode_to_vec().into()),
+      }),
+    }
+  }
+
+  #[test]
+  fn test_create_denom() {
+    let mut deps = mock_dependencies();
+    let env = mock_env();
+    let info = mock_info("dao", &coins(100, "uosmo"));
+
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), create_msg("amalgam")).unwrap();
+    assert_eq!(res.messages[0].id, CREATE_DENOM_REPLY_ID);
+
+    let res = reply(deps.as_mut(), env.clone(), create_denom_reply("factory/cosmos2contract/other"));
+    assert!(matches!(res, Err(ContractError::DenomMismatch { .. })));
+
+    reply(deps.as_mut(), env.clone(), create_denom_reply("factory/cosmos2contract/amalgam")).unwrap();
+    let tftoken = get_tftoken(deps.as_ref().storage, &env, 1).unwrap();
+    assert_eq!(tftoken.denom(), "factory/cosmos2contract/amalgam");
+    assert_eq!(tftoken.subdenom(), "amalgam");
+
+    // further Amalgams get their own id and denom
+    execute(deps.as_mut(), env.clone(), info, create_msg("other")).unwrap();
+    reply(deps.as_mut(), env.clone(), create_denom_reply("factory/cosmos2contract/other")).unwrap();
+    let tftoken = get_tftoken(deps.as_ref().storage, &env, 2).unwrap();
+    assert_eq!(tftoken.denom(), "factory/cosmos2contract/other");
+
+    let res: AmalgamsResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::Amalgams {
+      start_after: None,
+      limit: None,
+    }).unwrap()).unwrap();
+    assert_eq!(res.amalgams.iter().map(|amalgam| amalgam.id).collect::<Vec<_>>(), vec![1, 2]);
+    assert!(matches!(get_tftoken(deps.as_ref().storage, &env, 3), Err(ContractError::UnknownAmalgam)));
+  }
+
+  #[test]
+  fn test_instantiate_funds() {
+    let mut deps = mock_dependencies();
+
+    let res = instantiate(deps.as_mut(), mock_env(), mock_info("creator", &coins(100, "uosmo")), InstantiateMsg { factory: None });
+    assert!(matches!(res, Err(ContractError::InstantiateFunds)));
+
+    instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), InstantiateMsg { factory: None }).unwrap();
+  }
+
+  #[test]
+  fn test_creation_fee() {
+    let mut deps = mock_dependencies();
+    let env = mock_env();
+
+    let res = execute(deps.as_mut(), env.clone(), mock_info("dao", &coins(99, "uosmo")), create_msg("amalgam"));
+    assert!(matches!(res, Err(ContractError::InsufficientCreationFee)));
+
+    let res = execute(deps.as_mut(), env.clone(), mock_info("dao", &coins(100, "uatom")), create_msg("amalgam"));
+    assert!(matches!(res, Err(ContractError::InsufficientCreationFee)));
+
+    let info = mock_info("dao", &[coin(150, "uosmo"), coin(10, "uatom")]);
+    let res = execute(deps.as_mut(), env, info, create_msg("amalgam")).unwrap();
+    assert_eq!(res.messages.last().unwrap().msg, CosmosMsg::Bank(BankMsg::Send {
+      to_address: "dao".to_string(),
+      amount: vec![coin(50, "uosmo"), coin(10, "uatom")],
+    }));
+  }
+
+  #[test]
+  fn test_migrate_from_first_release() {
+    use amalgam_utils::asset::AssetInfo;
+    use cosmwasm_std::{Addr, ContractResult as QueryResult, Decimal, Order, Uint128, WasmQuery};
+
+    use crate::conversion::{ConversionFunction, Ratio};
+    use crate::state::{AMALGAMS, AMALGAM_SEQ, BALANCES, COMPONENTS, MINTED, STAKING, SUPPLY, TAXES};
+
+    let mut deps = mock_dependencies();
+    let mut env = mock_env();
+    let denom = "factory/cosmos2contract/amalgam";
+    let uosmo = AssetInfo::Native("uosmo".to_string());
+    let token = AssetInfo::Cw20(Addr::unchecked("token"));
+
+    // 50 uosmo of withdrawal taxes are held above the tracked balance
+    deps.querier.0.update_balance(env.contract.address.clone(), coins(1050, "uosmo"));
+    deps.querier.0.update_balance("user", coins(700, denom));
+    deps.querier.0.update_wasm(|query| match query {
+      WasmQuery::Smart { .. } => SystemResult::Ok(QueryResult::Ok(
+        to_json_binary(&cw20::BalanceResponse { balance: Uint128::new(200) }).unwrap(),
+      )),
+      _ => SystemResult::Err(SystemError::Unknown {}),
+    });
+
+    let storage = deps.as_mut().storage;
+    legacy::STATE.save(storage, &legacy::State { admin: "admin".to_string() }).unwrap();
+    legacy::COMPONENTS.save(storage, "native:uosmo".to_string(), &legacy::Component {
+      token: legacy::Asset::Native("uosmo".to_string()),
+      weight: Decimal::percent(50),
+      withdrawal_tax: 100,
+    }).unwrap();
+    legacy::COMPONENTS.save(storage, "cw20:token".to_string(), &legacy::Component {
+      token: legacy::Asset::Cw20("token".to_string()),
+      weight: Decimal::one(),
+      withdrawal_tax: 0,
+    }).unwrap();
+    legacy::BALANCES.save(storage, "native:uosmo".to_string(), &Uint128::new(1000)).unwrap();
+    legacy::BALANCES.save(storage, "cw20:token".to_string(), &Uint128::new(200)).unwrap();
+
+    let res = migrate(deps.as_mut(), env.clone(), MigrateMsg {}).unwrap();
+    assert!(res.attributes.iter().any(|attr| attr.key == "migrated_state" && attr.value == "true"));
+    assert!(res.attributes.iter().any(|attr| attr.key == "rekeyed" && attr.value == "2"));
+
+    let storage = deps.as_ref().storage;
+    assert_eq!(AMALGAM_SEQ.load(storage).unwrap(), 1);
+    let state = AMALGAMS.load(storage, 1).unwrap();
+    assert_eq!(state.admin, "admin");
+    assert_eq!(state.denom, denom);
+    assert_eq!(get_tftoken(storage, &env, 1).unwrap().denom(), denom);
+    assert_eq!(SUPPLY.load(storage, 1).unwrap(), Uint128::new(700));
+    assert_eq!(STAKING.load(storage, 1).unwrap().dao, Addr::unchecked("admin"));
+    assert!(!legacy::STATE.exists(storage));
+    let keys = COMPONENTS.prefix(1).keys(storage, None, None, Order::Ascending).collect::<StdResult<Vec<_>>>().unwrap();
+    assert_eq!(keys, vec![token.clone(), uosmo.clone()]);
+    let component = COMPONENTS.load(storage, (1, &uosmo)).unwrap();
+    assert_eq!(component.conversion, ConversionFunction::Linear(Ratio::percent(50)));
+    assert_eq!(component.withdrawal_tax, 100);
+    assert_eq!(BALANCES.load(storage, (1, &uosmo)).unwrap(), Uint128::new(1000));
+    assert_eq!(BALANCES.may_load_at_height(storage, (1, &uosmo), env.block.height + 1).unwrap(), Some(Uint128::new(1000)));
+    assert_eq!(BALANCES.load(storage, (1, &token)).unwrap(), Uint128::new(200));
+    assert_eq!(MINTED.load(storage, (1, &uosmo)).unwrap(), Uint128::new(500));
+    assert_eq!(MINTED.load(storage, (1, &token)).unwrap(), Uint128::new(200));
+    assert_eq!(TAXES.load(storage, (1, &uosmo)).unwrap(), Uint128::new(50));
+    assert_eq!(TAXES.may_load(storage, (1, &token)).unwrap(), None);
+    assert_eq!(legacy::COMPONENTS.keys_raw(storage, None, None, Order::Ascending)
+      .filter(|key| key.starts_with(b"native:") || key.starts_with(b"cw20:")).count(), 0);
+
+    // nothing is left to move
+    env.block.height += 1;
+    let res = migrate(deps.as_mut(), env, MigrateMsg {}).unwrap();
+    assert!(res.attributes.iter().any(|attr| attr.key == "migrated_state" && attr.value == "false"));
+    assert!(res.attributes.iter().any(|attr| attr.key == "rekeyed" && attr.value == "0"));
+    assert_eq!(AMALGAM_SEQ.load(deps.as_ref().storage).unwrap(), 1);
+    assert_eq!(COMPONENTS.prefix(1).keys_raw(deps.as_ref().storage, None, None, Order::Ascending).count(), 2);
+  }
 }
@@ -0,0 +1,819 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use amalgam_utils::asset::AssetInfo;
+use amalgam_utils::pool::{self, PoolReserves};
+use amalgam_utils::pricing;
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{
+  Decimal, Deps, Env, Fraction, Isqrt, QuerierWrapper, StdError, StdResult, Timestamp, Uint128, Uint256, Uint512,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{ContractError, ContractResult};
+
+/// How a component converts into Amalgam.
+///
+/// Every function describes the total amount of Amalgam backed by a tracked component balance. Deposits mint the
+/// difference of this integral before and after the deposit, and withdrawals invert it, so depositing and withdrawing
+/// is path-independent. Rates are exact [`Ratio`]s and all rounding is in favor of the protocol: deposits mint rounded
+/// down, withdrawals release rounded down.
+#[cw_serde]
+pub enum ConversionFunction {
+  /// Constant rate of Amalgam per unit of component.
+  Linear(Ratio),
+  /// Rate depending on the component's tracked balance. Each segment's rate applies from its `from` balance up to the
+  /// next segment's. The first segment must start at zero.
+  PiecewiseLinear(Vec<Segment>),
+  /// Diminishing returns: a tracked balance `b` backs `scale * sqrt(b)` Amalgam.
+  Sqrt(Ratio),
+  /// Constant rate of Amalgam per unit of component, provided by an external source at the time of the deposit or
+  /// withdrawal.
+  Source(ConversionSource),
+  /// Constant rate of Amalgam per unit of component at the time of the deposit, interpolated linearly between the
+  /// points of the schedule. Before the first and after the last point, their respective weight applies.
+  ///
+  /// Withdrawals release the position of the withdrawer at the rate it was minted at, so deposits at different weights
+  /// never fund each other. Weights must not increase over time.
+  Schedule(Vec<SchedulePoint>),
+}
+
+#[cw_serde]
+pub struct SchedulePoint {
+  pub time: Timestamp,
+  pub weight: Ratio,
+}
+
+#[cw_serde]
+pub struct Segment {
+  pub from: Uint128,
+  pub rate: Ratio,
+}
+
+/// Exact non-negative rational number, always kept in lowest terms.
+///
+/// Serialized as `{"numerator": "1", "denominator": "3"}`. Decimal strings such as `"0.5"` are accepted as well, so
+/// weights stored before rates became exact keep loading.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct Ratio {
+  numerator: Uint256,
+  denominator: Uint256,
+}
+
+impl Ratio {
+  /// Panics if `denominator` is zero.
+  pub fn from_ratio(numerator: impl Into<Uint256>, denominator: impl Into<Uint256>) -> Self {
+    Self::checked_from_ratio(numerator.into(), denominator.into()).expect("denominator must not be zero")
+  }
+
+  pub fn zero() -> Self {
+    Self::from_ratio(0u64, 1u64)
+  }
+
+  pub fn one() -> Self {
+    Self::from_ratio(1u64, 1u64)
+  }
+
+  pub fn percent(percent: u64) -> Self {
+    Self::from_ratio(percent, 100u64)
+  }
+
+  pub fn numerator(&self) -> Uint256 {
+    self.numerator
+  }
+
+  pub fn denominator(&self) -> Uint256 {
+    self.denominator
+  }
+
+  pub fn is_zero(&self) -> bool {
+    self.numerator.is_zero()
+  }
+
+  pub fn checked_mul(&self, other: &Ratio) -> StdResult<Ratio> {
+    let numerator = self.numerator.checked_mul(other.numerator)?;
+    let denominator = self.denominator.checked_mul(other.denominator)?;
+    Ok(Self::reduce(numerator, denominator))
+  }
+
+  pub fn checked_add(&self, other: &Ratio) -> StdResult<Ratio> {
+    let denominator = lcm(self.denominator, other.denominator)?;
+    let numerator = self.numerator.checked_mul(denominator / self.denominator)?
+      .checked_add(other.numerator.checked_mul(denominator / other.denominator)?)?;
+    Ok(Self::reduce(numerator, denominator))
+  }
+
+  /// `amount * self`, rounded down.
+  pub fn mul_floor(&self, amount: Uint128) -> StdResult<Uint128> {
+    let product = self.numerator.full_mul(amount) / Uint512::from(self.denominator);
+    Ok(Uint128::try_from(Uint256::try_from(product)?)?)
+  }
+
+  /// `amount * self`, rounded up.
+  pub fn mul_ceil(&self, amount: Uint128) -> StdResult<Uint128> {
+    let product = div_ceil(self.numerator.full_mul(amount), self.denominator.into())?;
+    Ok(Uint128::try_from(Uint256::try_from(product)?)?)
+  }
+
+  fn checked_from_ratio(numerator: Uint256, denominator: Uint256) -> Option<Self> {
+    (!denominator.is_zero()).then(|| Self::reduce(numerator, denominator))
+  }
+
+  fn reduce(numerator: Uint256, denominator: Uint256) -> Self {
+    let divisor = gcd(numerator, denominator);
+    Self { numerator: numerator / divisor, denominator: denominator / divisor }
+  }
+}
+
+impl From<Decimal> for Ratio {
+  fn from(value: Decimal) -> Self {
+    Self::from_ratio(value.numerator(), value.denominator())
+  }
+}
+
+impl PartialOrd for Ratio {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for Ratio {
+  fn cmp(&self, other: &Self) -> Ordering {
+    self.numerator.full_mul(other.denominator).cmp(&other.numerator.full_mul(self.denominator))
+  }
+}
+
+impl fmt::Display for Ratio {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}/{}", self.numerator, self.denominator)
+  }
+}
+
+impl<'de> Deserialize<'de> for Ratio {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct Fraction {
+      numerator: Uint256,
+      denominator: Uint256,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+      Decimal(Decimal),
+      Fraction(Fraction),
+    }
+
+    match Repr::deserialize(deserializer)? {
+      Repr::Decimal(value) => Ok(value.into()),
+      Repr::Fraction(Fraction { numerator, denominator }) => Ratio::checked_from_ratio(numerator, denominator)
+        .ok_or_else(|| serde::de::Error::custom("ratio denominator must not be zero")),
+    }
+  }
+}
+
+/// Number of decimals of the display units of a component and of its Amalgam.
+#[cw_serde]
+#[derive(Copy)]
+pub struct Decimals {
+  pub component: u32,
+  pub amalgam: u32,
+}
+
+/// Maximum ratio of `max_rate` to `min_rate` of pool sources. Pools are valued by their spot reserves, which can be
+/// moved within a transaction, so the bounds limit what such manipulation can extract.
+pub const MAX_POOL_RATE_SPREAD: Decimal = Decimal::percent(120);
+
+/// External provider of a conversion rate. Staleness and bounds are fixed when the component is added, so the admin
+/// cannot change them later.
+#[cw_serde]
+pub enum ConversionSource {
+  /// Query the rate from a pricing contract implementing [`pricing::PricingQueryMsg`].
+  Contract {
+    addr: String,
+    /// Maximum age of the rate in seconds.
+    max_staleness: u64,
+    min_rate: Decimal,
+    max_rate: Decimal,
+  },
+  /// Value LP shares of an Astroport-style pair contract by the amount of `reference` backing them, multiplied by
+  /// `weight`. The spot reserves can be manipulated, so `max_rate` must be within [`MAX_POOL_RATE_SPREAD`] of
+  /// `min_rate`.
+  AstroportPool {
+    pair: String,
+    reference: AssetInfo,
+    weight: Ratio,
+    min_rate: Decimal,
+    max_rate: Decimal,
+  },
+  /// Value LP shares of an Osmosis GAMM pool by the amount of the `reference` denom backing them, multiplied by
+  /// `weight`. The spot reserves can be manipulated, so `max_rate` must be within [`MAX_POOL_RATE_SPREAD`] of
+  /// `min_rate`.
+  OsmosisPool {
+    pool_id: u64,
+    reference: String,
+    weight: Ratio,
+    min_rate: Decimal,
+    max_rate: Decimal,
+  },
+}
+
+impl ConversionSource {
+  /// Validate the source for a component of `token`. Pool sources must value the LP token of their pool.
+  pub fn validate(&self, deps: Deps, token: &AssetInfo) -> ContractResult<()> {
+    let (min_rate, max_rate) = match self {
+      ConversionSource::Contract { addr, min_rate, max_rate, .. } => {
+        deps.api.addr_validate(addr)?;
+        return validate_bounds(min_rate, max_rate);
+      }
+      ConversionSource::AstroportPool { pair, reference, weight, min_rate, max_rate } => {
+        deps.api.addr_validate(pair)?;
+        if let AssetInfo::Cw20(contract) = reference {
+          deps.api.addr_validate(contract.as_str())?;
+        }
+        if weight.is_zero() {
+          return Err(ContractError::InvalidConversionFunction);
+        }
+        if pool::astroport::query_liquidity_token(&deps.querier, pair)? != token.id() {
+          return Err(ContractError::PoolTokenMismatch);
+        }
+        (min_rate, max_rate)
+      }
+      ConversionSource::OsmosisPool { pool_id, weight, min_rate, max_rate, .. } => {
+        if weight.is_zero() {
+          return Err(ContractError::InvalidConversionFunction);
+        }
+        if *token != AssetInfo::Native(format!("gamm/pool/{}", pool_id)) {
+          return Err(ContractError::PoolTokenMismatch);
+        }
+        (min_rate, max_rate)
+      }
+    };
+    validate_bounds(min_rate, max_rate)?;
+    if *max_rate > min_rate.checked_mul(MAX_POOL_RATE_SPREAD)? {
+      return Err(ContractError::InvalidConversionFunction);
+    }
+    Ok(())
+  }
+
+  pub fn rate(&self, querier: &QuerierWrapper, env: &Env, token: &AssetInfo) -> ContractResult<Ratio> {
+    let (rate, min_rate, max_rate) = match self {
+      ConversionSource::Contract { addr, max_staleness, min_rate, max_rate } => {
+        let res = pricing::query_rate(querier, addr, token.id())?;
+        if env.block.time.seconds().saturating_sub(res.updated_at.seconds()) > *max_staleness {
+          return Err(ContractError::StaleRate);
+        }
+        (Ratio::from(res.rate), min_rate, max_rate)
+      }
+      ConversionSource::AstroportPool { pair, reference, weight, min_rate, max_rate } => {
+        let reserves = pool::astroport::query_reserves(querier, pair)?;
+        (pool_rate(&reserves, reference.id(), weight)?, min_rate, max_rate)
+      }
+      ConversionSource::OsmosisPool { pool_id, reference, weight, min_rate, max_rate } => {
+        let reserves = pool::osmosis::query_reserves(querier, *pool_id)?;
+        (pool_rate(&reserves, reference, weight)?, min_rate, max_rate)
+      }
+    };
+
+    if rate < Ratio::from(*min_rate) || rate > Ratio::from(*max_rate) {
+      return Err(ContractError::RateOutOfBounds);
+    }
+    Ok(rate)
+  }
+}
+
+fn validate_bounds(min_rate: &Decimal, max_rate: &Decimal) -> ContractResult<()> {
+  if min_rate.is_zero() || min_rate > max_rate {
+    return Err(ContractError::InvalidConversionFunction);
+  }
+  Ok(())
+}
+
+/// Amalgam per LP share: the reference reserve backing a single share, multiplied by `weight`.
+fn pool_rate(reserves: &PoolReserves, reference: &str, weight: &Ratio) -> ContractResult<Ratio> {
+  let reserve = reserves.reserve(reference).ok_or(ContractError::UnknownReferenceAsset)?;
+  if reserves.total_shares.is_zero() {
+    return Err(ContractError::RateOutOfBounds);
+  }
+  Ok(Ratio::from_ratio(reserve, reserves.total_shares).checked_mul(weight)?)
+}
+
+impl ConversionFunction {
+  pub fn validate(&self, deps: Deps, token: &AssetInfo) -> ContractResult<()> {
+    match self {
+      ConversionFunction::Linear(weight) => {
+        if weight.is_zero() {
+          return Err(ContractError::InvalidConversionFunction);
+        }
+      }
+      ConversionFunction::PiecewiseLinear(segments) => {
+        if !segments.first().is_some_and(|segment| segment.from.is_zero()) {
+          return Err(ContractError::InvalidConversionFunction);
+        }
+        if segments.windows(2).any(|pair| pair[0].from >= pair[1].from) {
+          return Err(ContractError::InvalidConversionFunction);
+        }
+        if segments.iter().all(|segment| segment.rate.is_zero()) {
+          return Err(ContractError::InvalidConversionFunction);
+        }
+      }
+      ConversionFunction::Sqrt(scale) => {
+        if scale.is_zero() {
+          return Err(ContractError::InvalidConversionFunction);
+        }
+      }
+      ConversionFunction::Source(source) => source.validate(deps, token)?,
+      ConversionFunction::Schedule(points) => {
+        if points.is_empty() || points.iter().any(|point| point.weight.is_zero()) {
+          return Err(ContractError::InvalidConversionFunction);
+        }
+        if points.windows(2).any(|pair| pair[0].time >= pair[1].time || pair[0].weight < pair[1].weight) {
+          return Err(ContractError::InvalidConversionFunction);
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Resolve external sources into a fixed conversion function for the current block.
+  pub fn resolve(&self, querier: &QuerierWrapper, env: &Env, token: &AssetInfo) -> ContractResult<ConversionFunction> {
+    match self {
+      ConversionFunction::Source(source) =>
+        Ok(ConversionFunction::Linear(source.rate(querier, env, token)?)),
+      ConversionFunction::Schedule(points) =>
+        Ok(ConversionFunction::Linear(scheduled_weight(points, env.block.time)?)),
+      _ => Ok(self.clone()),
+    }
+  }
+
+  /// Convert a resolved conversion function from display units of the component and the Amalgam into base units.
+  pub fn to_base_units(&self, decimals: &Decimals) -> StdResult<ConversionFunction> {
+    let component = pow10(decimals.component)?;
+    let amalgam = pow10(decimals.amalgam)?;
+    // Amalgam base units per component base unit
+    let scale = Ratio::from_ratio(amalgam, component);
+    Ok(match self {
+      ConversionFunction::Linear(weight) => ConversionFunction::Linear(weight.checked_mul(&scale)?),
+      ConversionFunction::PiecewiseLinear(segments) => ConversionFunction::PiecewiseLinear(segments.iter()
+        .map(|segment| Ok(Segment {
+          from: Uint128::try_from(Uint256::from(segment.from).checked_mul(component)?)?,
+          rate: segment.rate.checked_mul(&scale)?,
+        }))
+        .collect::<StdResult<_>>()?),
+      ConversionFunction::Sqrt(factor) => {
+        // scale * sqrt(b / component) * amalgam == scale * amalgam / sqrt(component) * sqrt(b). The square root of the
+        // component's decimals is rounded up, in favor of the protocol.
+        let square = component.checked_mul(SQRT_PRECISION)?.checked_mul(SQRT_PRECISION)?;
+        let mut root = square.isqrt();
+        if root.checked_mul(root)? < square {
+          root += Uint256::one();
+        }
+        let scale = Ratio::from_ratio(amalgam.checked_mul(SQRT_PRECISION)?, root);
+        ConversionFunction::Sqrt(factor.checked_mul(&scale)?)
+      }
+      ConversionFunction::Source(_) | ConversionFunction::Schedule(_) =>
+        return Err(StdError::generic_err("conversion function must be resolved first")),
+    })
+  }
+
+  /// Amount of Amalgam minted when depositing `amount` on top of the tracked `balance`. Rounds down, in favor of the
+  /// protocol.
+  pub fn deposit(&self, balance: Uint128, amount: Uint128) -> StdResult<Uint128> {
+    let before = self.integral(balance, Rounding::Up)?;
+    let after = self.integral(balance.checked_add(amount)?, Rounding::Down)?;
+    let minted = after.saturating_sub(before) / Uint512::from(self.denominator()?);
+    Ok(Uint128::try_from(Uint256::try_from(minted)?)?)
+  }
+
+  /// Amount of component released when burning `amount` Amalgam against the tracked `balance`. Rounds down, in favor
+  /// of the protocol.
+  pub fn withdraw(&self, balance: Uint128, amount: Uint128) -> ContractResult<Uint128> {
+    let burnt = Uint512::from(amount).checked_mul(self.denominator()?.into())?;
+    // deposits already round the integral before them up, so rounding down here cannot release more than was deposited
+    let target = self.integral(balance, Rounding::Down)?
+      .checked_sub(burnt)
+      .map_err(|_| ContractError::InsufficientBalance)?;
+
+    // smallest remaining balance that still backs the remaining Amalgam
+    let remaining = self.inverse(target)?;
+    balance.checked_sub(remaining).map_err(|_| ContractError::InsufficientBalance)
+  }
+
+  /// Denominator of `integral`. Fixed per function, so integrals of different balances can be subtracted exactly.
+  fn denominator(&self) -> StdResult<Uint256> {
+    match self {
+      ConversionFunction::Linear(weight) => Ok(weight.denominator()),
+      ConversionFunction::PiecewiseLinear(segments) => segments.iter()
+        .try_fold(Uint256::one(), |acc, segment| lcm(acc, segment.rate.denominator())),
+      ConversionFunction::Sqrt(scale) => Ok(scale.denominator().checked_mul(SQRT_PRECISION)?),
+      ConversionFunction::Source(_) | ConversionFunction::Schedule(_) =>
+        Err(StdError::generic_err("conversion function must be resolved first")),
+    }
+  }
+
+  /// Total Amalgam backed by a tracked `balance`, as numerator over `denominator`. Exact, except for `Sqrt`, which is
+  /// rounded in the given direction.
+  fn integral(&self, balance: Uint128, rounding: Rounding) -> StdResult<Uint512> {
+    let denominator = self.denominator()?;
+    Ok(match self {
+      ConversionFunction::Linear(weight) =>
+        Uint512::from(balance).checked_mul(weight.numerator().into())?,
+      ConversionFunction::PiecewiseLinear(segments) => {
+        let mut total = Uint512::zero();
+        for (idx, segment) in segments.iter().enumerate() {
+          if balance <= segment.from {
+            break;
+          }
+          let end = segments.get(idx + 1).map_or(balance, |next| next.from.min(balance));
+          total = total.checked_add(Uint512::from(end - segment.from).checked_mul(rate(segment, denominator)?)?)?;
+        }
+        total
+      }
+      ConversionFunction::Sqrt(scale) => {
+        // scale * sqrt(balance) * denominator == sqrt(balance * (scale numerator * precision)^2)
+        let factor = scale.numerator().full_mul(SQRT_PRECISION);
+        let square = Uint512::from(balance).checked_mul(factor)?.checked_mul(factor)?;
+        let root = square.isqrt();
+        match rounding {
+          Rounding::Up if root.checked_mul(root)? < square => root + Uint512::one(),
+          _ => root,
+        }
+      }
+      ConversionFunction::Source(_) | ConversionFunction::Schedule(_) =>
+        return Err(StdError::generic_err("conversion function must be resolved first")),
+    })
+  }
+
+  /// Smallest balance whose integral, rounded down, reaches `target`.
+  fn inverse(&self, target: Uint512) -> ContractResult<Uint128> {
+    let value = match self {
+      ConversionFunction::Linear(weight) => div_ceil(target, weight.numerator().into())?,
+      ConversionFunction::PiecewiseLinear(segments) => {
+        let denominator = self.denominator()?;
+        let mut total = Uint512::zero();
+        let mut result = None;
+        for (idx, segment) in segments.iter().enumerate() {
+          if total >= target {
+            result = Some(Uint512::from(segment.from));
+            break;
+          }
+          if segment.rate.is_zero() {
+            continue;
+          }
+          let rate = rate(segment, denominator)?;
+          let needed = div_ceil(target - total, rate)?;
+          match segments.get(idx + 1) {
+            Some(next) if Uint512::from(next.from - segment.from) < needed => {
+              total = total.checked_add(Uint512::from(next.from - segment.from).checked_mul(rate)?)?;
+            }
+            _ => {
+              result = Some(Uint512::from(segment.from).checked_add(needed)?);
+              break;
+            }
+          }
+        }
+        result.ok_or(ContractError::InsufficientBalance)?
+      }
+      ConversionFunction::Sqrt(scale) => {
+        let factor = scale.numerator().full_mul(SQRT_PRECISION);
+        div_ceil(target.checked_mul(target)?, factor.checked_mul(factor)?)?
+      }
+      ConversionFunction::Source(_) | ConversionFunction::Schedule(_) =>
+        return Err(StdError::generic_err("conversion function must be resolved first").into()),
+    };
+    Uint256::try_from(value).ok()
+      .and_then(|value| Uint128::try_from(value).ok())
+      .ok_or(ContractError::InsufficientBalance)
+  }
+}
+
+#[derive(Clone, Copy)]
+enum Rounding {
+  Down,
+  Up,
+}
+
+/// Fixed-point precision of `Sqrt` integrals, the only ones which cannot be computed exactly.
+const SQRT_PRECISION: Uint256 = Uint256::from_u128(1_000_000_000_000_000_000);
+
+/// Rate of a segment, as numerator over the `denominator` of its function.
+fn rate(segment: &Segment, denominator: Uint256) -> StdResult<Uint512> {
+  Ok(segment.rate.numerator().full_mul(denominator / segment.rate.denominator()))
+}
+
+fn scheduled_weight(points: &[SchedulePoint], time: Timestamp) -> StdResult<Ratio> {
+  let next = points.iter().position(|point| point.time > time);
+  match next {
+    None => Ok(points[points.len() - 1].weight.clone()),
+    Some(0) => Ok(points[0].weight.clone()),
+    Some(idx) => {
+      let (start, end) = (&points[idx - 1], &points[idx]);
+      let elapsed = time.nanos() - start.time.nanos();
+      let duration = end.time.nanos() - start.time.nanos();
+      start.weight.checked_mul(&Ratio::from_ratio(duration - elapsed, duration))?
+        .checked_add(&end.weight.checked_mul(&Ratio::from_ratio(elapsed, duration))?)
+    }
+  }
+}
+
+fn pow10(exponent: u32) -> StdResult<Uint256> {
+  Ok(Uint256::from(10u64).checked_pow(exponent)?)
+}
+
+fn div_ceil(numerator: Uint512, denominator: Uint512) -> StdResult<Uint512> {
+  let quotient = numerator.checked_div(denominator)?;
+  if numerator.checked_rem(denominator)?.is_zero() {
+    Ok(quotient)
+  } else {
+    Ok(quotient + Uint512::one())
+  }
+}
+
+fn gcd(mut a: Uint256, mut b: Uint256) -> Uint256 {
+  while !b.is_zero() {
+    (a, b) = (b, a % b);
+  }
+  a
+}
+
+fn lcm(a: Uint256, b: Uint256) -> StdResult<Uint256> {
+  Ok((a / gcd(a, b)).checked_mul(b)?)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use cosmwasm_std::testing::{mock_dependencies, mock_env, MockQuerier};
+  use cosmwasm_std::{from_json, to_json_binary, ContractResult, Empty, SystemResult};
+  use proptest::prelude::*;
+
+  use crate::state::Component;
+
+  fn curves() -> Vec<ConversionFunction> {
+    vec![
+      ConversionFunction::Linear(Ratio::from_ratio(3u64, 7u64)),
+      ConversionFunction::PiecewiseLinear(vec![
+        Segment { from: Uint128::zero(), rate: Ratio::one() },
+        Segment { from: Uint128::from(1000u64), rate: Ratio::percent(50) },
+        Segment { from: Uint128::from(5000u64), rate: Ratio::percent(10) },
+      ]),
+      ConversionFunction::Sqrt(Ratio::from_ratio(5u64, 2u64)),
+    ]
+  }
+
+  #[test]
+  fn test_validate() {
+    let deps = mock_dependencies();
+    let token = AssetInfo::Native("uosmo".to_string());
+    assert!(ConversionFunction::Linear(Ratio::zero()).validate(deps.as_ref(), &token).is_err());
+    assert!(ConversionFunction::Sqrt(Ratio::zero()).validate(deps.as_ref(), &token).is_err());
+    assert!(ConversionFunction::PiecewiseLinear(vec![]).validate(deps.as_ref(), &token).is_err());
+    assert!(ConversionFunction::PiecewiseLinear(vec![
+      Segment { from: Uint128::from(1u64), rate: Ratio::one() },
+    ]).validate(deps.as_ref(), &token).is_err());
+    assert!(ConversionFunction::PiecewiseLinear(vec![
+      Segment { from: Uint128::zero(), rate: Ratio::one() },
+      Segment { from: Uint128::zero(), rate: Ratio::one() },
+    ]).validate(deps.as_ref(), &token).is_err());
+    for curve in curves() {
+      assert!(curve.validate(deps.as_ref(), &token).is_ok());
+    }
+
+    // pool sources must value the shares of their own pool
+    let source = ConversionFunction::Source(ConversionSource::OsmosisPool {
+      pool_id: 1,
+      reference: "uosmo".to_string(),
+      weight: Ratio::one(),
+      min_rate: Decimal::one(),
+      max_rate: Decimal::one(),
+    });
+    assert!(matches!(source.validate(deps.as_ref(), &token), Err(ContractError::PoolTokenMismatch)));
+    assert!(source.validate(deps.as_ref(), &AssetInfo::Native("gamm/pool/2".to_string())).is_err());
+    assert!(source.validate(deps.as_ref(), &AssetInfo::Native("gamm/pool/1".to_string())).is_ok());
+
+    // spot reserves can be manipulated, so pool rates must be tightly bounded
+    let source = |max_rate| ConversionFunction::Source(ConversionSource::OsmosisPool {
+      pool_id: 1,
+      reference: "uosmo".to_string(),
+      weight: Ratio::one(),
+      min_rate: Decimal::one(),
+      max_rate,
+    });
+    let token = AssetInfo::Native("gamm/pool/1".to_string());
+    assert!(source(Decimal::percent(120)).validate(deps.as_ref(), &token).is_ok());
+    assert!(source(Decimal::permille(1201)).validate(deps.as_ref(), &token).is_err());
+  }
+
+  #[test]
+  fn test_path_independent() {
+    for curve in curves() {
+      let split = curve.deposit(Uint128::zero(), Uint128::from(1234u64)).unwrap()
+        + curve.deposit(Uint128::from(1234u64), Uint128::from(6000u64)).unwrap();
+      let whole = curve.deposit(Uint128::zero(), Uint128::from(7234u64)).unwrap();
+      // splitting a deposit only ever loses rounding dust
+      assert!(split <= whole && whole - split <= Uint128::one());
+    }
+  }
+
+  #[test]
+  fn test_withdraw_never_exceeds_deposit() {
+    for curve in curves() {
+      let balance = Uint128::from(3000u64);
+      let amount = Uint128::from(4321u64);
+      let minted = curve.deposit(balance, amount).unwrap();
+      let released = curve.withdraw(balance + amount, minted).unwrap();
+      assert!(released <= amount);
+      // rounding costs the depositor at most one unit of Amalgam
+      assert!(curve.deposit(balance, amount - released).unwrap() <= Uint128::one());
+    }
+  }
+
+  #[test]
+  fn test_source_staleness() {
+    let mut querier = MockQuerier::<Empty>::new(&[]);
+    let updated_at = mock_env().block.time;
+    querier.update_wasm(move |_| {
+      let res = pricing::RateResponse { rate: Decimal::one(), updated_at };
+      SystemResult::Ok(ContractResult::Ok(to_json_binary(&res).unwrap()))
+    });
+    let querier = QuerierWrapper::new(&querier);
+    let token = AssetInfo::Native("uosmo".to_string());
+    let source = |max_staleness| ConversionSource::Contract {
+      addr: "pricing".to_string(),
+      max_staleness,
+      min_rate: Decimal::one(),
+      max_rate: Decimal::one(),
+    };
+
+    let mut env = mock_env();
+    env.block.time = updated_at.plus_seconds(61);
+    assert!(matches!(source(60).rate(&querier, &env, &token), Err(ContractError::StaleRate)));
+    assert_eq!(source(61).rate(&querier, &env, &token).unwrap(), Ratio::one());
+    // huge staleness bounds must not overflow
+    assert_eq!(source(u64::MAX).rate(&querier, &env, &token).unwrap(), Ratio::one());
+  }
+
+  #[test]
+  fn test_schedule() {
+    let start = Timestamp::from_seconds(1000);
+    let points = vec![
+      SchedulePoint { time: start, weight: Ratio::percent(300) },
+      SchedulePoint { time: start.plus_seconds(100), weight: Ratio::percent(100) },
+      SchedulePoint { time: start.plus_seconds(200), weight: Ratio::percent(50) },
+    ];
+    let deps = mock_dependencies();
+    let token = AssetInfo::Native("uosmo".to_string());
+    assert!(ConversionFunction::Schedule(points.clone()).validate(deps.as_ref(), &token).is_ok());
+    assert!(ConversionFunction::Schedule(vec![]).validate(deps.as_ref(), &token).is_err());
+    assert!(ConversionFunction::Schedule(vec![points[1].clone(), points[0].clone()]).validate(deps.as_ref(), &token).is_err());
+    // increasing weights would let later depositors withdraw part of earlier deposits
+    let increasing = vec![points[0].clone(), SchedulePoint { time: start.plus_seconds(100), weight: Ratio::percent(400) }];
+    assert!(ConversionFunction::Schedule(increasing).validate(deps.as_ref(), &token).is_err());
+
+    assert_eq!(scheduled_weight(&points, Timestamp::from_seconds(0)).unwrap(), Ratio::percent(300));
+    assert_eq!(scheduled_weight(&points, start.plus_seconds(25)).unwrap(), Ratio::percent(250));
+    assert_eq!(scheduled_weight(&points, start.plus_seconds(100)).unwrap(), Ratio::percent(100));
+    assert_eq!(scheduled_weight(&points, start.plus_seconds(150)).unwrap(), Ratio::percent(75));
+    assert_eq!(scheduled_weight(&points, start.plus_seconds(1000)).unwrap(), Ratio::percent(50));
+  }
+
+  #[test]
+  fn test_sqrt_diminishing() {
+    let curve = ConversionFunction::Sqrt(Ratio::one());
+    let small = curve.deposit(Uint128::zero(), Uint128::from(10000u64)).unwrap();
+    let large = curve.deposit(Uint128::zero(), Uint128::from(1000000u64)).unwrap();
+    assert_eq!(small, Uint128::from(100u64));
+    assert_eq!(large, Uint128::from(1000u64));
+  }
+
+  #[test]
+  fn test_ratio() {
+    let third = Ratio::from_ratio(2u64, 6u64);
+    assert_eq!((third.numerator(), third.denominator()), (Uint256::one(), Uint256::from(3u64)));
+    assert_eq!(Ratio::from(Decimal::percent(50)), Ratio::from_ratio(1u64, 2u64));
+    assert!(Ratio::from(Decimal::from_ratio(1u64, 3u64)) < third);
+    assert_eq!(third.checked_add(&Ratio::from_ratio(1u64, 6u64)).unwrap(), Ratio::percent(50));
+    assert_eq!(third.checked_mul(&Ratio::from_ratio(3u64, 4u64)).unwrap(), Ratio::from_ratio(1u64, 4u64));
+    assert_eq!(third.mul_floor(Uint128::from(5u64)).unwrap(), Uint128::one());
+    assert_eq!(third.mul_ceil(Uint128::from(5u64)).unwrap(), Uint128::from(2u64));
+    assert_eq!(third.mul_ceil(Uint128::from(6u64)).unwrap(), Uint128::from(2u64));
+
+    assert_eq!(from_json::<Ratio>(br#"{"numerator":"2","denominator":"6"}"#).unwrap(), third);
+    assert_eq!(from_json::<Ratio>(br#""0.25""#).unwrap(), Ratio::percent(25));
+    assert!(from_json::<Ratio>(br#"{"numerator":"1","denominator":"0"}"#).is_err());
+    assert!(from_json::<Ratio>(br#"{"numerator":"1","denominator":"2","extra":"3"}"#).is_err());
+    assert_eq!(cosmwasm_std::to_json_string(&third).unwrap(), r#"{"numerator":"1","denominator":"3"}"#);
+  }
+
+  #[test]
+  fn test_exact_rates() {
+    // a third is not representable as a decimal, so withdrawing used to round against the user
+    let curve = ConversionFunction::Linear(Ratio::from_ratio(1u64, 3u64));
+    assert_eq!(curve.deposit(Uint128::zero(), Uint128::from(300u64)).unwrap(), Uint128::from(100u64));
+    assert_eq!(curve.withdraw(Uint128::from(300u64), Uint128::from(100u64)).unwrap(), Uint128::from(300u64));
+    assert_eq!(curve.withdraw(Uint128::from(300u64), Uint128::from(1u64)).unwrap(), Uint128::from(3u64));
+
+    // dust rounds down for the depositor
+    assert_eq!(curve.deposit(Uint128::zero(), Uint128::from(2u64)).unwrap(), Uint128::zero());
+    assert_eq!(curve.withdraw(Uint128::from(2u64), Uint128::zero()).unwrap(), Uint128::zero());
+    assert!(matches!(curve.withdraw(Uint128::from(2u64), Uint128::one()), Err(ContractError::InsufficientBalance)));
+  }
+
+  #[test]
+  fn test_to_base_units() {
+    let decimals = Decimals { component: 18, amalgam: 6 };
+    assert_eq!(
+      ConversionFunction::Linear(Ratio::percent(50)).to_base_units(&decimals).unwrap(),
+      ConversionFunction::Linear(Ratio::from_ratio(1u64, 2_000_000_000_000u64)),
+    );
+    assert_eq!(
+      ConversionFunction::PiecewiseLinear(vec![
+        Segment { from: Uint128::zero(), rate: Ratio::one() },
+        Segment { from: Uint128::from(1000u64), rate: Ratio::percent(10) },
+      ]).to_base_units(&decimals).unwrap(),
+      ConversionFunction::PiecewiseLinear(vec![
+        Segment { from: Uint128::zero(), rate: Ratio::from_ratio(1u64, 1_000_000_000_000u64) },
+        Segment { from: Uint128::from(1_000_000_000_000_000_000_000u128), rate: Ratio::from_ratio(1u64, 10_000_000_000_000u64) },
+      ]),
+    );
+
+    // a balance of 4 display units backs 2 display units of Amalgam
+    let sqrt = ConversionFunction::Sqrt(Ratio::one());
+    let base = sqrt.to_base_units(&decimals).unwrap();
+    assert_eq!(base.deposit(Uint128::zero(), Uint128::from(4_000_000_000_000_000_000u128)).unwrap(), Uint128::from(2_000_000u64));
+    // odd decimals cannot be scaled exactly and round in favor of the protocol
+    let base = sqrt.to_base_units(&Decimals { component: 3, amalgam: 0 }).unwrap();
+    assert_eq!(base.deposit(Uint128::zero(), Uint128::from(4000u64)).unwrap(), Uint128::one());
+
+    assert!(ConversionFunction::Linear(Ratio::one()).to_base_units(&Decimals { component: 100, amalgam: 0 }).is_err());
+  }
+
+  fn curve_strategy() -> impl Strategy<Value = ConversionFunction> {
+    let ratio = (1u128..1_000_000, 1u128..1_000_000).prop_map(|(n, d)| Ratio::from_ratio(n, d));
+    prop_oneof![
+      ratio.clone().prop_map(ConversionFunction::Linear),
+      prop::collection::vec((1u128..1_000_000, ratio.clone()), 1..5).prop_map(|steps| {
+        let mut from = 0u128;
+        steps.into_iter().map(|(width, rate)| {
+          let segment = Segment { from: Uint128::from(from), rate };
+          from += width;
+          segment
+        }).collect::<Vec<_>>()
+      }).prop_map(ConversionFunction::PiecewiseLinear),
+      ratio.clone().prop_map(ConversionFunction::Sqrt),
+      // non-increasing weights, a point every 100 seconds from the mock block time
+      prop::collection::vec(ratio, 1..5).prop_map(|mut weights| {
+        weights.sort_by(|a, b| b.cmp(a));
+        let start = mock_env().block.time;
+        weights.into_iter().enumerate()
+          .map(|(idx, weight)| SchedulePoint { time: start.plus_seconds(100 * idx as u64), weight })
+          .collect::<Vec<_>>()
+      }).prop_map(ConversionFunction::Schedule),
+    ]
+  }
+
+  fn amount_strategy() -> impl Strategy<Value = u128> {
+    prop_oneof![0u128..1_000, 0u128..1_000_000_000_000_000_000]
+  }
+
+  proptest! {
+    #[test]
+    fn prop_deposit_withdraw_never_creates_value(
+      curve in curve_strategy(),
+      earlier in prop::collection::vec(amount_strategy(), 0..4),
+      amount in amount_strategy(),
+    ) {
+      let component = Component {
+        token: AssetInfo::Native("uosmo".to_string()),
+        conversion: curve,
+        withdrawal_tax: 0,
+        deposit_fee: None,
+        decimals: None,
+      };
+      let querier = MockQuerier::<Empty>::new(&[]);
+      let querier = QuerierWrapper::new(&querier);
+      let mut env = mock_env();
+
+      // earlier depositors, one every 70 seconds so schedules move between and past their points
+      let (mut balance, mut minted) = (Uint128::zero(), Uint128::zero());
+      for deposit in earlier {
+        let (net, fee) = component.simulate_deposit(&querier, &env, balance, Uint128::from(deposit)).unwrap();
+        balance += Uint128::from(deposit);
+        minted += net + fee;
+        env.block.time = env.block.time.plus_seconds(70);
+      }
+
+      let amount = Uint128::from(amount);
+      let (own, fee) = component.simulate_deposit(&querier, &env, balance, amount).unwrap();
+      // schedule components release the position of the depositor, other components the whole balance
+      let released = match component.conversion {
+        ConversionFunction::Schedule(_) => component.simulate_withdraw(&querier, &env, amount, own, own).unwrap(),
+        _ => component.simulate_withdraw(&querier, &env, balance + amount, minted + own + fee, own).unwrap(),
+      };
+      prop_assert!(released <= amount);
+    }
+  }
+}
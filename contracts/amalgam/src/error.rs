@@ -1,4 +1,4 @@
-use cosmwasm_std::StdError;
+use cosmwasm_std::{CheckedFromRatioError, OverflowError, StdError};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -6,6 +6,12 @@ pub enum ContractError {
   #[error("{0}")]
   Std(#[from] StdError),
 
+  #[error("{0}")]
+  Overflow(#[from] OverflowError),
+
+  #[error("{0}")]
+  CheckedFromRatio(#[from] CheckedFromRatioError),
+
   #[error("Unauthorized")]
   Unauthorized,
 
@@ -15,18 +21,96 @@ pub enum ContractError {
   #[error("Duplicate component")]
   DuplicateComponent,
 
+  #[error("An Amalgam cannot be a component of itself")]
+  SelfComponent,
+
   #[error("Unknown asset")]
   UnknownAsset,
 
   #[error("Invalid fee must be between 0 and 10000")]
   InvalidWithdrawalFee,
 
+  #[error("Invalid fee must be between 0 and 10000")]
+  InvalidDepositFee,
+
+  #[error("Invalid conversion function")]
+  InvalidConversionFunction,
+
+  #[error("Decimals are read by the contract, set display_units instead")]
+  DecimalsNotAllowed,
+
+  #[error("Display units are not supported for source conversions")]
+  DisplayUnitsNotSupported,
+
+  #[error("Conversion rate is stale")]
+  StaleRate,
+
+  #[error("Conversion rate out of bounds")]
+  RateOutOfBounds,
+
+  #[error("Component is not the LP token of the pool")]
+  PoolTokenMismatch,
+
+  #[error("Reference asset not found in pool")]
+  UnknownReferenceAsset,
+
   #[error("No taxes to collect")]
   NoTaxes,
 
   #[error("Insufficient contract balance")]
   InsufficientBalance,
 
+  #[error("Insufficient staked balance")]
+  InsufficientStake,
+
+  #[error("Too many pending claims, claim some before unstaking more")]
+  TooManyClaims,
+
+  #[error("Nothing to claim")]
+  NothingToClaim,
+
+  #[error("Locking is disabled")]
+  LockingDisabled,
+
+  #[error("Invalid lock duration")]
+  InvalidLockDuration,
+
+  #[error("Lock already exists")]
+  LockExists,
+
+  #[error("No lock")]
+  NoLock,
+
+  #[error("Lock expired")]
+  LockExpired,
+
+  #[error("Lock not expired")]
+  LockNotExpired,
+
+  #[error("Only native components can be withdrawn over IBC")]
+  IbcTransferNotSupported,
+
+  #[error("Unknown IBC transfer")]
+  UnknownTransfer,
+
+  #[error("Contract is paused")]
+  Paused,
+
+  #[error("Instantiation does not accept funds, send the denom creation fee with CreateAmalgam")]
+  InstantiateFunds,
+
+  #[error("Funds do not cover the denom creation fee")]
+  InsufficientCreationFee,
+
+  #[error("Tokenfactory created denom {actual}, expected {expected}")]
+  DenomMismatch { expected: String, actual: String },
+
+  #[error("Unknown Amalgam")]
+  UnknownAmalgam,
+
+  #[error("Deposits through ibc-hooks must set a recipient")]
+  MissingRecipient,
+
   // Add any other custom errors you like here.
   // Look at https://docs.rs/thiserror/1.0.21/thiserror/ for details.
 }
@@ -1,13 +1,22 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
-use cosmwasm_std::{from_json, Addr, Decimal, DepsMut, Env, Fraction, MessageInfo, Response, Uint128};
-
-use crate::contract::get_tftoken;
+use cosmwasm_std::{
+  coin, from_json, to_json_binary, to_json_string, Addr, BankMsg, DepsMut, Env, MessageInfo, Order, Response, StdResult,
+  SubMsg, Uint128, WasmMsg,
+};
+use cw_utils::Duration;
+
+use crate::contract::{get_tftoken, CREATE_DENOM_REPLY_ID, IBC_TRANSFER_REPLY_ID};
+use crate::conversion::{ConversionFunction, Decimals, Ratio};
 use crate::{ContractError, ContractResult};
-use crate::msg::{Cw20ReceivePayload, ExecuteMsg, UpdateMetadataMsg};
-use crate::state::{Asset, Component, COMPONENTS, STATE, BALANCES};
+use crate::msg::{Cw20ReceivePayload, ExecuteMsg, FactoryExecuteMsg, IbcDestination, Lock, UpdateMetadataMsg};
+use crate::state::{positions, Claim, Component, HistoryAction, HistoryEntry, Position, StakingConfig, State, AMALGAMS, AMALGAM_SEQ, COMPONENTS, FACTORY, HISTORY, HISTORY_SEQ, BALANCES, DONATIONS, MINTED, SUPPLY, TAXES, CLAIMS, MAX_CLAIMS, STAKED_BALANCES, STAKED_TOTAL, STAKING, LOCKED, LOCKS, VOTE_ESCROW, IbcTransfer, PAUSED, PENDING_AMALGAM, PENDING_TRANSFER, REFUNDS};
+use crate::ve::{self, LockedAsset, VeLock, VoteEscrowConfig};
 
-use amalgam_utils::tokenfactory::{DenomMetadata, DenomUnit, TFToken};
+use amalgam_utils::asset::{Asset, AssetInfo, AssetInfoUnchecked, AssetList};
+use amalgam_utils::ibc_hooks::{is_intermediate_sender, CallbackMemo, MsgTransfer};
+use amalgam_utils::tokenfactory::osmosis::query_params;
+use amalgam_utils::tokenfactory::{self, Coin as TFCoin, DenomMetadata, TFToken};
 
 struct ExecuteContext<'a> {
   deps: DepsMut<'a>,
@@ -15,6 +24,18 @@ struct ExecuteContext<'a> {
   info: MessageInfo,
 }
 
+/// Settings of a new Amalgam, as passed to `ExecuteMsg::CreateAmalgam`.
+struct AmalgamSettings {
+  subdenom: String,
+  metadata: DenomMetadata,
+  admin: String,
+  dao: Option<String>,
+  fee_recipient: Option<String>,
+  unstaking_duration: Option<Duration>,
+  track_history: Option<bool>,
+  vote_escrow: Option<VoteEscrowConfig>,
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn execute(
   deps: DepsMut,
@@ -24,86 +45,229 @@ pub fn execute(
 ) -> ContractResult<Response> {
   let mut ctx = ExecuteContext { deps, env, info: info.clone() };
 
+  // admins stay able to manage their Amalgams while the contract is paused
+  let admin_msg = matches!(
+    msg,
+    ExecuteMsg::AddComponent { .. }
+      | ExecuteMsg::UpdateMetadata { .. }
+      | ExecuteMsg::CollectTaxes { .. }
+      | ExecuteMsg::UpdateAdmin { .. }
+      | ExecuteMsg::UpdateFeeRecipient { .. }
+      | ExecuteMsg::TransferDenomAdmin { .. }
+  );
+  if !admin_msg && PAUSED.may_load(ctx.deps.storage)?.unwrap_or(false) {
+    return Err(ContractError::Paused);
+  }
+
   match msg {
-    ExecuteMsg::AddComponent(component) =>
-      add_component(&mut ctx, component),
-    ExecuteMsg::UpdateMetadata(metadata) =>
-      update_metadata(&mut ctx, metadata),
+    ExecuteMsg::CreateAmalgam { subdenom, metadata, admin, dao, fee_recipient, unstaking_duration, track_history, vote_escrow } =>
+      create_amalgam(&mut ctx, AmalgamSettings {
+        subdenom,
+        metadata,
+        admin,
+        dao,
+        fee_recipient,
+        unstaking_duration,
+        track_history,
+        vote_escrow,
+      }),
+    ExecuteMsg::AddComponent { amalgam, component, display_units } =>
+      add_component(&mut ctx, amalgam, component, display_units.unwrap_or(false)),
+    ExecuteMsg::UpdateMetadata { amalgam, metadata } =>
+      update_metadata(&mut ctx, amalgam, metadata),
     ExecuteMsg::Receive(msg) => {
       let payload: Cw20ReceivePayload = from_json(&msg.msg)?;
       match payload {
-        Cw20ReceivePayload::Deposit {} =>
+        Cw20ReceivePayload::Deposit { amalgam, lock, recipient } =>
           deposit_cw20(
             &mut ctx,
+            amalgam,
             info.sender.clone(),
             msg.amount,
-            msg.sender.to_string(),
-          )
+            recipient.unwrap_or(msg.sender),
+            lock,
+          ),
+        Cw20ReceivePayload::IncreaseLock { amalgam } => {
+          let component = helpers::load_component(&ctx, amalgam, &AssetInfo::Cw20(info.sender.clone()))?;
+          let owner = ctx.deps.api.addr_validate(&msg.sender)?;
+          increase_lock(&mut ctx, amalgam, component, msg.amount, owner)
+        }
+        Cw20ReceivePayload::Donate { amalgam } => {
+          let component = helpers::load_component(&ctx, amalgam, &AssetInfo::Cw20(info.sender.clone()))?;
+          let donor = ctx.deps.api.addr_validate(&msg.sender)?;
+          donate(&mut ctx, amalgam, component, msg.amount, donor)
+        }
       }
     }
-    ExecuteMsg::Deposit {} =>
-      deposit_native(&mut ctx),
-    ExecuteMsg::Withdraw { asset } =>
-      withdraw(&mut ctx, asset),
-    ExecuteMsg::CollectTaxes { asset } =>
-      collect_taxes(&mut ctx, asset),
-    ExecuteMsg::UpdateAdmin { admin } =>
-      update_admin(&mut ctx, admin),
-  }
-}
-
-fn add_component(ctx: &mut ExecuteContext, component: Component) -> ContractResult<Response> {
-  helpers::assert_admin(ctx)?;
-
-  let key = match component.token.clone() {
-    Asset::Native(denom) => {
-      let key = format!("native:{}", denom);
-      let existing = COMPONENTS.may_load(ctx.deps.storage, key.clone())?;
-      if existing.is_some() {
-        return Err(ContractError::DuplicateComponent);
+    ExecuteMsg::Deposit { amalgam, lock, recipient } => {
+      // ibc-hooks deposits from an intermediary address nobody controls, which must not receive the Amalgam
+      let recipient = match recipient {
+        Some(recipient) => recipient,
+        None if is_intermediate_sender(&ctx.deps.querier, &info.sender) => return Err(ContractError::MissingRecipient),
+        None => info.sender.to_string(),
+      };
+      deposit_native(&mut ctx, amalgam, lock, recipient)
+    }
+    ExecuteMsg::Donate { amalgam } => {
+      if info.funds.len() != 1 {
+        return Err(ContractError::InvalidFunds);
       }
-      key
+      let component = helpers::load_component(&ctx, amalgam, &AssetInfo::Native(info.funds[0].denom.clone()))?;
+      donate(&mut ctx, amalgam, component, info.funds[0].amount, info.sender)
     }
-    Asset::Cw20(contract) => {
-      let key = format!("cw20:{}", contract);
-      let existing = COMPONENTS.may_load(ctx.deps.storage, key.clone())?;
-      if existing.is_some() {
-        return Err(ContractError::DuplicateComponent);
+    ExecuteMsg::Withdraw { amalgam, asset, ibc } =>
+      withdraw(&mut ctx, amalgam, asset, ibc),
+    ExecuteMsg::CollectTaxes { amalgam, asset } =>
+      collect_taxes(&mut ctx, amalgam, asset),
+    ExecuteMsg::UpdateAdmin { amalgam, admin } =>
+      update_admin(&mut ctx, amalgam, admin),
+    ExecuteMsg::UpdateFeeRecipient { amalgam, fee_recipient } =>
+      update_fee_recipient(&mut ctx, amalgam, fee_recipient),
+    ExecuteMsg::TransferDenomAdmin { amalgam, new_admin } =>
+      transfer_denom_admin(&mut ctx, amalgam, new_admin),
+    ExecuteMsg::Stake { amalgam } =>
+      stake(&mut ctx, amalgam),
+    ExecuteMsg::Unstake { amalgam, amount } =>
+      unstake(&mut ctx, amalgam, amount),
+    ExecuteMsg::Claim { amalgam } =>
+      claim(&mut ctx, amalgam),
+    ExecuteMsg::IncreaseLock { amalgam } => {
+      if info.funds.len() != 1 {
+        return Err(ContractError::InvalidFunds);
       }
-      key
+      let component = helpers::load_component(&ctx, amalgam, &AssetInfo::Native(info.funds[0].denom.clone()))?;
+      increase_lock(&mut ctx, amalgam, component, info.funds[0].amount, info.sender)
     }
+    ExecuteMsg::ExtendLock { amalgam, duration } =>
+      extend_lock(&mut ctx, amalgam, duration),
+    ExecuteMsg::WithdrawExpired { amalgam } =>
+      withdraw_expired(&mut ctx, amalgam),
+  }
+}
+
+fn create_amalgam(ctx: &mut ExecuteContext, settings: AmalgamSettings) -> ContractResult<Response> {
+  // the factory registers its instances as holding the single Amalgam it creates on them
+  if FACTORY.may_load(ctx.deps.storage)?.is_some_and(|factory| factory != ctx.info.sender) {
+    return Err(ContractError::Unauthorized);
+  }
+
+  let amalgam = AMALGAM_SEQ.may_load(ctx.deps.storage)?.unwrap_or_default() + 1;
+  AMALGAM_SEQ.save(ctx.deps.storage, &amalgam)?;
+
+  // the expected denom, verified against the one reported by tokenfactory in `reply`
+  let tftoken = tokenfactory::osmosis::TFToken::new(ctx.env.contract.address.clone(), settings.subdenom);
+
+  AMALGAMS.save(ctx.deps.storage, amalgam, &State {
+    admin: ctx.deps.api.addr_validate(&settings.admin)?.to_string(),
+    fee_recipient: settings.fee_recipient
+      .map(|addr| ctx.deps.api.addr_validate(&addr))
+      .transpose()?
+      .map(|addr| addr.to_string()),
+    track_history: settings.track_history.unwrap_or(false),
+    denom: tftoken.denom(),
+  })?;
+  PENDING_AMALGAM.save(ctx.deps.storage, &amalgam)?;
+
+  let dao = match settings.dao {
+    Some(dao) => ctx.deps.api.addr_validate(&dao)?,
+    None => ctx.info.sender.clone(),
   };
+  STAKING.save(ctx.deps.storage, amalgam, &StakingConfig {
+    dao,
+    unstaking_duration: settings.unstaking_duration,
+  })?;
+
+  if let Some(vote_escrow) = settings.vote_escrow {
+    if vote_escrow.max_lock_duration == 0 {
+      return Err(ContractError::InvalidLockDuration);
+    }
+    VOTE_ESCROW.save(ctx.deps.storage, amalgam, &vote_escrow)?;
+  }
+
+  let creation_fee = query_params(&ctx.deps.querier)?.creation_fee()?;
+  let excess = tokenfactory::deduct_fee(ctx.info.funds.clone(), &creation_fee)
+    .ok_or(ContractError::InsufficientCreationFee)?;
+
+  // the metadata is only set once the reply confirmed the denom
+  let mut res = Response::new()
+    .add_attribute("action", "create_amalgam")
+    .add_attribute("amalgam", amalgam.to_string())
+    .add_submessages(tftoken.create().into_iter().map(|msg| SubMsg::reply_on_success(msg, CREATE_DENOM_REPLY_ID)))
+    .add_messages(tftoken.set_metadata(settings.metadata));
+
+  if !excess.is_empty() {
+    res = res.add_message(BankMsg::Send { to_address: ctx.info.sender.to_string(), amount: excess });
+  }
+
+  Ok(res)
+}
+
+fn add_component(ctx: &mut ExecuteContext, amalgam: u64, component: Component<String>, display_units: bool) -> ContractResult<Response> {
+  helpers::assert_admin(ctx, amalgam)?;
+  let state = helpers::load_amalgam(ctx, amalgam)?;
+
+  let mut component = component.check(ctx.deps.api)?;
+  if component.token == AssetInfo::Native(state.denom.clone()) {
+    return Err(ContractError::SelfComponent);
+  }
+  component.token.assert_exists(&ctx.deps.querier)?;
+
+  if COMPONENTS.has(ctx.deps.storage, (amalgam, &component.token)) {
+    return Err(ContractError::DuplicateComponent);
+  }
 
   if component.withdrawal_tax > 10000 {
     return Err(ContractError::InvalidWithdrawalFee);
   }
 
-  COMPONENTS.save(ctx.deps.storage, key, &component)?;
+  if component.deposit_fee.unwrap_or(0) > 10000 {
+    return Err(ContractError::InvalidDepositFee);
+  }
 
-  Ok(Response::new()
-    .add_attribute("action", "add_component")
-  )
+  component.conversion.validate(ctx.deps.as_ref(), &component.token)?;
+
+  // external sources provide their rates as they are, and their bounds are compared before any scaling
+  if display_units && matches!(component.conversion, ConversionFunction::Source(_)) {
+    return Err(ContractError::DisplayUnitsNotSupported);
+  }
+
+  if display_units {
+    component.decimals = Some(Decimals {
+      component: component.token.query_decimals(&ctx.deps.querier)?,
+      amalgam: tokenfactory::query_denom_metadata(&ctx.deps.querier, state.denom)?.display_exponent()?,
+    });
+  }
+
+  COMPONENTS.save(ctx.deps.storage, (amalgam, &component.token), &component)?;
+
+  let mut res = Response::new()
+    .add_attribute("action", "add_component");
+
+  // the factory indexes its instances by component
+  if let Some(factory) = FACTORY.may_load(ctx.deps.storage)? {
+    res = res.add_message(WasmMsg::Execute {
+      contract_addr: factory.to_string(),
+      msg: to_json_binary(&FactoryExecuteMsg::SyncInstance { address: ctx.env.contract.address.to_string() })?,
+      funds: vec![],
+    });
+  }
+
+  Ok(res)
 }
 
-fn update_metadata(ctx: &mut ExecuteContext, metadata: UpdateMetadataMsg) -> ContractResult<Response> {
-  helpers::assert_admin(ctx)?;
+fn update_metadata(ctx: &mut ExecuteContext, amalgam: u64, metadata: UpdateMetadataMsg) -> ContractResult<Response> {
+  helpers::assert_admin(ctx, amalgam)?;
 
-  let tftoken = get_tftoken(&ctx.env);
-  let existing = ctx.deps.querier.query_denom_metadata(tftoken.denom())?;
+  let tftoken = get_tftoken(ctx.deps.storage, &ctx.env, amalgam)?;
+  let existing = tokenfactory::query_denom_metadata(&ctx.deps.querier, tftoken.denom())?;
 
+  // essential metadata like symbol and denom units cannot be changed
   let new_metadata = DenomMetadata {
-    base: existing.base,
-    denom_units: existing.denom_units.iter().map(|unit| DenomUnit {
-      denom: unit.denom.clone(),
-      exponent: unit.exponent,
-      aliases: unit.aliases.clone(),
-    }).collect(),
-    display: existing.display,
     name: metadata.name.unwrap_or(existing.name),
     description: metadata.description.unwrap_or(existing.description),
-    symbol: existing.symbol,
     uri: metadata.uri.unwrap_or(existing.uri),
     uri_hash: metadata.uri_hash.unwrap_or(existing.uri_hash),
+    ..existing
   };
 
   Ok(Response::new()
@@ -112,114 +276,223 @@ fn update_metadata(ctx: &mut ExecuteContext, metadata: UpdateMetadataMsg) -> Con
   )
 }
 
-fn deposit_native(ctx: &mut ExecuteContext) -> ContractResult<Response> {
+fn deposit_native(ctx: &mut ExecuteContext, amalgam: u64, lock: Option<Lock>, recipient: String) -> ContractResult<Response> {
   if ctx.info.funds.len() != 1 {
     return Err(ContractError::InvalidFunds);
   }
-  let fund = &ctx.info.funds[0];
-  let component = COMPONENTS.may_load(ctx.deps.storage, format!("native:{}", fund.denom))?;
-  if component.is_none() {
-    return Err(ContractError::UnknownAsset);
+  let fund = ctx.info.funds[0].clone();
+  let component = helpers::load_component(ctx, amalgam, &AssetInfo::Native(fund.denom))?;
+  match lock {
+    Some(Lock { duration }) => {
+      let owner = ctx.deps.api.addr_validate(&recipient)?;
+      create_lock(ctx, amalgam, component, fund.amount, owner, duration)
+    }
+    None => deposit(ctx, amalgam, component, fund.amount, recipient),
   }
-  deposit(ctx, component.unwrap(), fund.amount, ctx.info.sender.to_string())
 }
 
-fn deposit_cw20(ctx: &mut ExecuteContext, token_contract: Addr, amount: Uint128, recipient: String) -> ContractResult<Response> {
-  let component = COMPONENTS.may_load(ctx.deps.storage, format!("cw20:{}", token_contract))?;
-  if component.is_none() {
-    return Err(ContractError::UnknownAsset);
+fn deposit_cw20(ctx: &mut ExecuteContext, amalgam: u64, token_contract: Addr, amount: Uint128, recipient: String, lock: Option<Lock>) -> ContractResult<Response> {
+  let component = helpers::load_component(ctx, amalgam, &AssetInfo::Cw20(token_contract))?;
+  match lock {
+    Some(Lock { duration }) => {
+      let owner = ctx.deps.api.addr_validate(&recipient)?;
+      create_lock(ctx, amalgam, component, amount, owner, duration)
+    }
+    None => deposit(ctx, amalgam, component, amount, recipient),
   }
-  deposit(ctx, component.unwrap(), amount, recipient)
 }
 
-fn deposit(ctx: &mut ExecuteContext, component: Component, amount: Uint128, recipient: String) -> ContractResult<Response> {
-  let tftoken = get_tftoken(&ctx.env);
+fn deposit(ctx: &mut ExecuteContext, amalgam: u64, component: Component, amount: Uint128, recipient: String) -> ContractResult<Response> {
+  let tftoken = get_tftoken(ctx.deps.storage, &ctx.env, amalgam)?;
+  let key = (amalgam, &component.token);
 
-  BALANCES.update(ctx.deps.storage, component.token.key(), |balance| -> Result<Uint128, ContractError> {
-    Ok(balance.unwrap_or(Uint128::zero()) + amount)
+  let balance = BALANCES.may_load(ctx.deps.storage, key)?.unwrap_or(Uint128::zero());
+  let (amount_net, fee) = component.simulate_deposit(&ctx.deps.querier, &ctx.env, balance, amount)?;
+
+  let height = ctx.env.block.height;
+  BALANCES.save(ctx.deps.storage, key, &(balance + amount), height)?;
+  MINTED.update(ctx.deps.storage, key, |minted| -> ContractResult<_> {
+    Ok(minted.unwrap_or_default() + amount_net + fee)
+  })?;
+  SUPPLY.update(ctx.deps.storage, amalgam, height, |supply| -> ContractResult<_> {
+    Ok(supply.unwrap_or_default() + amount_net + fee)
   })?;
 
-  Ok(Response::new()
+  let state = AMALGAMS.load(ctx.deps.storage, amalgam)?;
+  let fee_recipient = ctx.deps.api.addr_validate(&state.fee_recipient.unwrap_or(state.admin))?;
+
+  // schedule components release each position at the rate it was minted at, so the fee recipient's position holds the
+  // share of the deposit backing the fee
+  let fee_share = match &component.conversion {
+    ConversionFunction::Schedule(_) if !fee.is_zero() => amount.multiply_ratio(fee, amount_net + fee),
+    _ => Uint128::zero(),
+  };
+  let recipient_addr = ctx.deps.api.addr_validate(&recipient)?;
+  helpers::record(ctx, amalgam, &recipient_addr, &component.token, HistoryAction::Deposit, amount - fee_share, amount_net)?;
+  if !fee_share.is_zero() {
+    helpers::update_position(ctx, amalgam, &fee_recipient, &component.token, HistoryAction::Deposit, fee_share, fee)?;
+  }
+
+  let mut res = Response::new()
     .add_attribute("action", "deposit")
-    .add_messages(tftoken.mint(
-      amount * component.weight,
-      recipient,
-    ))
+    .add_attribute("recipient", &recipient)
+    .add_attribute("fee", fee)
+    .add_messages(tftoken.mint(amount_net, recipient));
+
+  // tokenfactory refuses to mint zero amounts
+  if !fee.is_zero() {
+    res = res.add_messages(tftoken.mint(fee, fee_recipient.to_string()));
+  }
+
+  Ok(res)
+}
+
+/// Add `amount` of the component to the tracked balance without minting Amalgam, strengthening its backing.
+fn donate(ctx: &mut ExecuteContext, amalgam: u64, component: Component, amount: Uint128, donor: Addr) -> ContractResult<Response> {
+  if amount.is_zero() {
+    return Err(ContractError::InvalidFunds);
+  }
+
+  BALANCES.update(ctx.deps.storage, (amalgam, &component.token), ctx.env.block.height, |balance| -> ContractResult<_> {
+    Ok(balance.unwrap_or_default().checked_add(amount)?)
+  })?;
+  DONATIONS.update(ctx.deps.storage, (amalgam, &donor, &component.token), |donated| -> ContractResult<_> {
+    Ok(donated.unwrap_or_default().checked_add(amount)?)
+  })?;
+
+  Ok(Response::new()
+    .add_attribute("action", "donate")
+    .add_attribute("donor", donor)
+    .add_attribute("amount", amount)
   )
 }
 
-fn withdraw(ctx: &mut ExecuteContext, asset: Asset) -> ContractResult<Response> {
-  let tftoken = get_tftoken(&ctx.env);
+fn withdraw(ctx: &mut ExecuteContext, amalgam: u64, asset: AssetInfoUnchecked, ibc: Option<IbcDestination>) -> ContractResult<Response> {
+  let tftoken = get_tftoken(ctx.deps.storage, &ctx.env, amalgam)?;
+  let asset = asset.check(ctx.deps.api)?;
 
   if ctx.info.funds.len() != 1 {
     return Err(ContractError::InvalidFunds);
   }
 
-  let fund = &ctx.info.funds[0];
+  let fund = ctx.info.funds[0].clone();
   if fund.denom != tftoken.denom() {
     return Err(ContractError::InvalidFunds);
   }
 
-  let component = COMPONENTS.may_load(ctx.deps.storage, asset.key())?;
+  if ibc.is_some() && !matches!(asset, AssetInfo::Native(_)) {
+    return Err(ContractError::IbcTransferNotSupported);
+  }
+
+  let key = (amalgam, &asset);
+  let component = COMPONENTS.may_load(ctx.deps.storage, key)?;
   if component.is_none() {
     return Err(ContractError::UnknownAsset);
   }
   let component = component.unwrap();
 
-  let withdrawal_tax_decimal = Decimal::from_ratio(component.withdrawal_tax as u64, 10000u64);
-
-  let amount_gross = fund.amount * Decimal::inv(&component.weight).unwrap();
+  let withdrawal_tax = Ratio::from_ratio(component.withdrawal_tax as u64, 10000u64);
 
-  let tax = amount_gross * withdrawal_tax_decimal;
+  let balance = BALANCES.may_load(ctx.deps.storage, key)?.unwrap_or(Uint128::zero());
+  let minted = MINTED.may_load(ctx.deps.storage, key)?.unwrap_or(Uint128::zero());
+  let amount_gross = match &component.conversion {
+    // only the sender's own position can be released, at the rate it was minted at
+    ConversionFunction::Schedule(_) => {
+      let position = positions().may_load(ctx.deps.storage, (amalgam, &ctx.info.sender, &asset))?;
+      let (deposited, position_minted) = position.map(|position| (position.deposited, position.minted)).unwrap_or_default();
+      component.simulate_withdraw(&ctx.deps.querier, &ctx.env, deposited, position_minted, fund.amount)?
+    }
+    _ => component.simulate_withdraw(&ctx.deps.querier, &ctx.env, balance, minted, fund.amount)?,
+  };
 
-  // apply withdrawal tax & subtract one to make sure we don't run out of funds due to precision loss
-  let amount_net = amount_gross - tax - Uint128::one();
+  // taxes round up, in favor of the protocol
+  let tax = withdrawal_tax.mul_ceil(amount_gross)?;
+  let amount_net = amount_gross - tax;
 
   // note: we need to subtract the gross amount from the balance, not the net amount
-  // this causes surplus between the actual balance and the tracked balance
-  // that we can then withdraw as taxes later to bring them back in line
-  BALANCES.update(ctx.deps.storage, asset.key(), |balance| -> Result<Uint128, ContractError> {
-    match balance {
-      Some(balance) => {
-        if balance < amount_gross {
-          return Err(ContractError::InsufficientBalance);
-        }
-        Ok(balance - amount_gross)
-      },
-      None =>
-        Err(ContractError::InsufficientBalance),
-    }
+  // the tax stays in the contract and is accrued separately, as other Amalgams may hold the same component
+  let height = ctx.env.block.height;
+  BALANCES.save(ctx.deps.storage, key, &(balance - amount_gross), height)?;
+  TAXES.update(ctx.deps.storage, key, |taxes| -> ContractResult<_> {
+    Ok(taxes.unwrap_or_default() + tax)
   })?;
+  SUPPLY.update(ctx.deps.storage, amalgam, height, |supply| -> ContractResult<_> {
+    Ok(supply.unwrap_or_default().checked_sub(fund.amount)?)
+  })?;
+  // Amalgam is fungible, so it may be burnt against a component other than the one it was minted against
+  MINTED.save(ctx.deps.storage, key, &minted.saturating_sub(fund.amount))?;
 
-  Ok(Response::new()
+  let sender = ctx.info.sender.clone();
+  helpers::record(ctx, amalgam, &sender, &asset, HistoryAction::Withdraw, amount_gross, fund.amount)?;
+
+  let res = Response::new()
     .add_attribute("action", "withdraw")
     // burn the sent tokens
-    .add_messages(tftoken.burn(fund.amount, ctx.env.contract.address.to_string()))
-    .add_message(asset.send(amount_net, ctx.info.sender.to_string()))
+    .add_messages(tftoken.burn(fund.amount, ctx.env.contract.address.to_string()));
+
+  let (Some(destination), AssetInfo::Native(denom)) = (ibc, &asset) else {
+    return Ok(res.add_message(Asset::new(asset, amount_net).transfer_msg(&ctx.info.sender)?));
+  };
+
+  // the packet sequence is only known from the reply, where the transfer is moved to `IBC_TRANSFERS`
+  PENDING_TRANSFER.save(ctx.deps.storage, &IbcTransfer {
+    amalgam,
+    sender,
+    asset: asset.clone(),
+    amount: amount_net,
+    channel: destination.channel.clone(),
+  })?;
+
+  let contract = ctx.env.contract.address.to_string();
+  let transfer = MsgTransfer {
+    source_port: "transfer".to_string(),
+    source_channel: destination.channel.clone(),
+    token: Some(TFCoin { denom: denom.clone(), amount: amount_net.to_string() }),
+    sender: contract.clone(),
+    receiver: destination.receiver.clone(),
+    timeout_height: None,
+    timeout_timestamp: ctx.env.block.time.plus_seconds(destination.timeout).nanos(),
+    memo: to_json_string(&CallbackMemo { ibc_callback: contract })?,
+  };
+
+  Ok(res
+    .add_attribute("channel", destination.channel)
+    .add_attribute("receiver", destination.receiver)
+    .add_submessage(SubMsg::reply_on_success(transfer, IBC_TRANSFER_REPLY_ID))
   )
 }
 
-fn collect_taxes(ctx: &mut ExecuteContext, asset: Asset) -> ContractResult<Response> {
-  let admin = helpers::assert_admin(ctx)?;
-
-  let balance_actual = asset.balance(&ctx.deps.as_ref(), &ctx.deps.querier, ctx.info.sender.clone());
-  let balance_tracked = BALANCES.may_load(ctx.deps.storage, asset.key())?.unwrap_or(Uint128::zero());
+fn collect_taxes(ctx: &mut ExecuteContext, amalgam: u64, asset: AssetInfoUnchecked) -> ContractResult<Response> {
+  let admin = helpers::assert_admin(ctx, amalgam)?;
+  let asset = asset.check(ctx.deps.api)?;
+  send_taxes(ctx.deps.branch(), amalgam, asset, admin.to_string())
+}
 
-  if balance_actual <= balance_tracked {
+/// Send the withdrawal taxes of `asset` accrued by `amalgam` to `recipient`. Shared with the governance override in
+/// `sudo`.
+pub(crate) fn send_taxes(deps: DepsMut, amalgam: u64, asset: AssetInfo, recipient: String) -> ContractResult<Response> {
+  let key = (amalgam, &asset);
+  let taxes = TAXES.may_load(deps.storage, key)?.unwrap_or_default();
+  if taxes.is_zero() {
     return Err(ContractError::NoTaxes);
   }
+  TAXES.remove(deps.storage, key);
 
   Ok(Response::new()
     .add_attribute("action", "collect_taxes")
-    .add_message(asset.send(balance_actual - balance_tracked, admin.to_string()))
+    .add_message(Asset::new(asset, taxes).transfer_msg(recipient)?)
   )
 }
 
-fn update_admin(ctx: &mut ExecuteContext, admin: String) -> ContractResult<Response> {
-  STATE.update(ctx.deps.storage, |mut state| {
-    if ctx.info.sender != state.admin {
-      return Err(ContractError::Unauthorized);
-    }
+fn update_admin(ctx: &mut ExecuteContext, amalgam: u64, admin: String) -> ContractResult<Response> {
+  helpers::assert_admin(ctx, amalgam)?;
+  set_admin(ctx.deps.branch(), amalgam, admin)
+}
+
+/// Replace the admin of `amalgam`. Shared with the governance override in `sudo`.
+pub(crate) fn set_admin(deps: DepsMut, amalgam: u64, admin: String) -> ContractResult<Response> {
+  AMALGAMS.update(deps.storage, amalgam, |state| -> ContractResult<_> {
+    let mut state = state.ok_or(ContractError::UnknownAmalgam)?;
     state.admin = admin.clone();
     Ok(state)
   })?;
@@ -229,26 +502,420 @@ fn update_admin(ctx: &mut ExecuteContext, admin: String) -> ContractResult<Respo
   )
 }
 
+fn update_fee_recipient(ctx: &mut ExecuteContext, amalgam: u64, fee_recipient: Option<String>) -> ContractResult<Response> {
+  helpers::assert_admin(ctx, amalgam)?;
+
+  let fee_recipient = fee_recipient
+    .map(|addr| ctx.deps.api.addr_validate(&addr))
+    .transpose()?
+    .map(|addr| addr.to_string());
+
+  AMALGAMS.update(ctx.deps.storage, amalgam, |state| -> ContractResult<_> {
+    let mut state = state.ok_or(ContractError::UnknownAmalgam)?;
+    state.fee_recipient = fee_recipient.clone();
+    Ok(state)
+  })?;
+
+  Ok(Response::new()
+    .add_attribute("action", "update_fee_recipient")
+    .add_attribute("new_fee_recipient", fee_recipient.unwrap_or_default())
+  )
+}
+
+fn transfer_denom_admin(ctx: &mut ExecuteContext, amalgam: u64, new_admin: String) -> ContractResult<Response> {
+  helpers::assert_admin(ctx, amalgam)?;
+
+  let new_admin = ctx.deps.api.addr_validate(&new_admin)?;
+  let tftoken = get_tftoken(ctx.deps.storage, &ctx.env, amalgam)?;
+
+  Ok(Response::new()
+    .add_attribute("action", "transfer_denom_admin")
+    .add_attribute("new_admin", new_admin.to_string())
+    .add_messages(tftoken.change_admin(new_admin.to_string()))
+  )
+}
+
+fn stake(ctx: &mut ExecuteContext, amalgam: u64) -> ContractResult<Response> {
+  let tftoken = get_tftoken(ctx.deps.storage, &ctx.env, amalgam)?;
+
+  if ctx.info.funds.len() != 1 || ctx.info.funds[0].denom != tftoken.denom() {
+    return Err(ContractError::InvalidFunds);
+  }
+  let amount = ctx.info.funds[0].amount;
+  let height = ctx.env.block.height;
+
+  STAKED_BALANCES.update(ctx.deps.storage, (amalgam, &ctx.info.sender), height, |balance| -> ContractResult<_> {
+    Ok(balance.unwrap_or_default() + amount)
+  })?;
+  STAKED_TOTAL.update(ctx.deps.storage, amalgam, height, |total| -> ContractResult<_> {
+    Ok(total.unwrap_or_default() + amount)
+  })?;
+
+  Ok(Response::new()
+    .add_attribute("action", "stake")
+    .add_attribute("amount", amount)
+  )
+}
+
+fn unstake(ctx: &mut ExecuteContext, amalgam: u64, amount: Uint128) -> ContractResult<Response> {
+  let tftoken = get_tftoken(ctx.deps.storage, &ctx.env, amalgam)?;
+  let height = ctx.env.block.height;
+
+  if amount.is_zero() {
+    return Err(ContractError::InsufficientStake);
+  }
+
+  STAKED_BALANCES.update(ctx.deps.storage, (amalgam, &ctx.info.sender), height, |balance| -> ContractResult<_> {
+    balance.unwrap_or_default()
+      .checked_sub(amount)
+      .map_err(|_| ContractError::InsufficientStake)
+  })?;
+  STAKED_TOTAL.update(ctx.deps.storage, amalgam, height, |total| -> ContractResult<_> {
+    Ok(total.unwrap_or_default().checked_sub(amount)?)
+  })?;
+
+  let config = STAKING.load(ctx.deps.storage, amalgam)?;
+  let res = Response::new()
+    .add_attribute("action", "unstake")
+    .add_attribute("amount", amount);
+
+  match config.unstaking_duration {
+    None => Ok(res.add_message(BankMsg::Send {
+      to_address: ctx.info.sender.to_string(),
+      amount: vec![coin(amount.u128(), tftoken.denom())],
+    })),
+    Some(duration) => {
+      let release_at = duration.after(&ctx.env.block);
+      CLAIMS.update(ctx.deps.storage, (amalgam, &ctx.info.sender), |claims| -> ContractResult<_> {
+        let mut claims = claims.unwrap_or_default();
+        if claims.len() >= MAX_CLAIMS {
+          return Err(ContractError::TooManyClaims);
+        }
+        claims.push(Claim { amount, release_at });
+        Ok(claims)
+      })?;
+      Ok(res.add_attribute("release_at", release_at.to_string()))
+    }
+  }
+}
+
+fn claim(ctx: &mut ExecuteContext, amalgam: u64) -> ContractResult<Response> {
+  let tftoken = get_tftoken(ctx.deps.storage, &ctx.env, amalgam)?;
+  let sender = ctx.info.sender.clone();
+
+  let claims = CLAIMS.may_load(ctx.deps.storage, (amalgam, &sender))?.unwrap_or_default();
+  let (released, pending): (Vec<_>, Vec<_>) = claims.into_iter()
+    .partition(|claim| claim.release_at.is_expired(&ctx.env.block));
+
+  let refunds = REFUNDS
+    .prefix((amalgam, &sender))
+    .range(ctx.deps.storage, None, None, Order::Ascending)
+    .collect::<StdResult<Vec<_>>>()?;
+
+  let amount: Uint128 = released.iter().map(|claim| claim.amount).sum();
+  if amount.is_zero() && refunds.is_empty() {
+    return Err(ContractError::NothingToClaim);
+  }
+
+  if pending.is_empty() {
+    CLAIMS.remove(ctx.deps.storage, (amalgam, &sender));
+  } else {
+    CLAIMS.save(ctx.deps.storage, (amalgam, &sender), &pending)?;
+  }
+
+  let mut res = Response::new()
+    .add_attribute("action", "claim")
+    .add_attribute("amount", amount);
+
+  if !amount.is_zero() {
+    res = res.add_message(BankMsg::Send {
+      to_address: sender.to_string(),
+      amount: vec![coin(amount.u128(), tftoken.denom())],
+    });
+  }
+
+  let mut assets = AssetList::new();
+  for (key, refund) in refunds {
+    REFUNDS.remove(ctx.deps.storage, (amalgam, &sender, &key));
+    assets.add(&Asset::new(refund.asset, refund.amount))?;
+  }
+
+  Ok(res.add_messages(assets.transfer_msgs(&sender)?))
+}
+
+fn create_lock(ctx: &mut ExecuteContext, amalgam: u64, component: Component, amount: Uint128, owner: Addr, duration: u64) -> ContractResult<Response> {
+  let config = helpers::vote_escrow(ctx, amalgam)?;
+  let height = ctx.env.block.height;
+
+  if duration == 0 || duration > config.max_lock_duration {
+    return Err(ContractError::InvalidLockDuration);
+  }
+  if LOCKS.may_load(ctx.deps.storage, (amalgam, &owner))?.is_some() {
+    return Err(ContractError::LockExists);
+  }
+
+  let value = helpers::lock_component(ctx, amalgam, &component, amount)?;
+  let lock = VeLock {
+    assets: vec![LockedAsset { asset: component.token.clone(), amount }],
+    amalgam: value,
+    end: height + duration,
+  };
+
+  LOCKS.save(ctx.deps.storage, (amalgam, &owner), &lock, height)?;
+  ve::checkpoint(ctx.deps.storage, &config, amalgam, height, None, Some(&lock))?;
+
+  Ok(Response::new()
+    .add_attribute("action", "lock")
+    .add_attribute("amalgam", value)
+    .add_attribute("end", lock.end.to_string())
+  )
+}
+
+fn increase_lock(ctx: &mut ExecuteContext, amalgam: u64, component: Component, amount: Uint128, owner: Addr) -> ContractResult<Response> {
+  let config = helpers::vote_escrow(ctx, amalgam)?;
+  let height = ctx.env.block.height;
+
+  let old = helpers::active_lock(ctx, amalgam, &owner)?;
+  let value = helpers::lock_component(ctx, amalgam, &component, amount)?;
+  let mut lock = old.clone();
+  lock.add(&component.token, amount, value);
+
+  LOCKS.save(ctx.deps.storage, (amalgam, &owner), &lock, height)?;
+  ve::checkpoint(ctx.deps.storage, &config, amalgam, height, Some(&old), Some(&lock))?;
+
+  Ok(Response::new()
+    .add_attribute("action", "increase_lock")
+    .add_attribute("amalgam", value)
+  )
+}
+
+fn extend_lock(ctx: &mut ExecuteContext, amalgam: u64, duration: u64) -> ContractResult<Response> {
+  let config = helpers::vote_escrow(ctx, amalgam)?;
+  let height = ctx.env.block.height;
+  let owner = ctx.info.sender.clone();
+
+  let old = helpers::active_lock(ctx, amalgam, &owner)?;
+  let end = height + duration;
+  if duration > config.max_lock_duration || end <= old.end {
+    return Err(ContractError::InvalidLockDuration);
+  }
+  let lock = VeLock { end, ..old.clone() };
+
+  LOCKS.save(ctx.deps.storage, (amalgam, &owner), &lock, height)?;
+  ve::checkpoint(ctx.deps.storage, &config, amalgam, height, Some(&old), Some(&lock))?;
+
+  Ok(Response::new()
+    .add_attribute("action", "extend_lock")
+    .add_attribute("end", end.to_string())
+  )
+}
+
+fn withdraw_expired(ctx: &mut ExecuteContext, amalgam: u64) -> ContractResult<Response> {
+  let height = ctx.env.block.height;
+  let owner = ctx.info.sender.clone();
+
+  let lock = LOCKS.may_load(ctx.deps.storage, (amalgam, &owner))?.ok_or(ContractError::NoLock)?;
+  if lock.end > height {
+    return Err(ContractError::LockNotExpired);
+  }
+
+  // expired locks have no power left, so the total power curve is unaffected
+  LOCKS.remove(ctx.deps.storage, (amalgam, &owner), height)?;
+  for locked in &lock.assets {
+    LOCKED.update(ctx.deps.storage, (amalgam, &locked.asset), |total| -> ContractResult<_> {
+      Ok(total.unwrap_or_default().saturating_sub(locked.amount))
+    })?;
+  }
+
+  let assets = AssetList::try_from(lock.assets.into_iter()
+    .map(|locked| Asset::new(locked.asset, locked.amount))
+    .collect::<Vec<_>>())?;
+
+  Ok(Response::new()
+    .add_attribute("action", "withdraw_expired")
+    .add_messages(assets.transfer_msgs(&owner)?)
+  )
+}
+
 mod helpers {
   use super::*;
 
-  pub fn assert_admin(ctx: &mut ExecuteContext) -> ContractResult<Addr> {
-    let state = STATE.load(ctx.deps.storage)?;
+  pub fn load_amalgam(ctx: &ExecuteContext, amalgam: u64) -> ContractResult<State> {
+    AMALGAMS.may_load(ctx.deps.storage, amalgam)?.ok_or(ContractError::UnknownAmalgam)
+  }
+
+  pub fn load_component(ctx: &ExecuteContext, amalgam: u64, asset: &AssetInfo) -> ContractResult<Component> {
+    COMPONENTS.may_load(ctx.deps.storage, (amalgam, asset))?.ok_or(ContractError::UnknownAsset)
+  }
+
+  pub fn vote_escrow(ctx: &ExecuteContext, amalgam: u64) -> ContractResult<VoteEscrowConfig> {
+    VOTE_ESCROW.may_load(ctx.deps.storage, amalgam)?.ok_or(ContractError::LockingDisabled)
+  }
+
+  pub fn active_lock(ctx: &ExecuteContext, amalgam: u64, owner: &Addr) -> ContractResult<VeLock> {
+    let lock = LOCKS.may_load(ctx.deps.storage, (amalgam, owner))?.ok_or(ContractError::NoLock)?;
+    if lock.end <= ctx.env.block.height {
+      return Err(ContractError::LockExpired);
+    }
+    Ok(lock)
+  }
+
+  /// Add `amount` of the component to the locked amount and return the Amalgam it is worth, before deposit fees, as if
+  /// deposited on top of the tracked balance and the amount already locked.
+  pub fn lock_component(ctx: &mut ExecuteContext, amalgam: u64, component: &Component, amount: Uint128) -> ContractResult<Uint128> {
+    let key = (amalgam, &component.token);
+    let balance = BALANCES.may_load(ctx.deps.storage, key)?.unwrap_or_default();
+    let locked = LOCKED.may_load(ctx.deps.storage, key)?.unwrap_or_default();
+    let (amount_net, fee) = component.simulate_deposit(&ctx.deps.querier, &ctx.env, balance.checked_add(locked)?, amount)?;
+    LOCKED.save(ctx.deps.storage, key, &locked.checked_add(amount)?)?;
+    Ok(amount_net + fee)
+  }
+
+  pub fn assert_admin(ctx: &mut ExecuteContext, amalgam: u64) -> ContractResult<Addr> {
+    let state = load_amalgam(ctx, amalgam)?;
     if ctx.info.sender != state.admin {
       return Err(ContractError::Unauthorized);
     }
     // sender can never be an invalid address, so `.unwrap()` is safe
     Ok(ctx.deps.api.addr_validate(&state.admin).unwrap())
   }
+
+  /// Update the position of `user` in `asset` of `amalgam` and append the action to the history if enabled.
+  pub fn record(
+    ctx: &mut ExecuteContext,
+    amalgam: u64,
+    user: &Addr,
+    asset: &AssetInfo,
+    action: HistoryAction,
+    amount: Uint128,
+    minted: Uint128,
+  ) -> ContractResult<()> {
+    update_position(ctx, amalgam, user, asset, action.clone(), amount, minted)?;
+
+    if load_amalgam(ctx, amalgam)?.track_history {
+      let id = HISTORY_SEQ.may_load(ctx.deps.storage)?.unwrap_or_default();
+      HISTORY_SEQ.save(ctx.deps.storage, &(id + 1))?;
+      HISTORY.save(ctx.deps.storage, (amalgam, user, id), &HistoryEntry {
+        id,
+        action,
+        asset: asset.clone(),
+        amount,
+        amalgam: minted,
+        height: ctx.env.block.height,
+        time: ctx.env.block.time,
+      })?;
+    }
+
+    Ok(())
+  }
+
+  /// Update the position of `user` in `asset` of `amalgam` without recording the action in the history.
+  pub fn update_position(
+    ctx: &mut ExecuteContext,
+    amalgam: u64,
+    user: &Addr,
+    asset: &AssetInfo,
+    action: HistoryAction,
+    amount: Uint128,
+    minted: Uint128,
+  ) -> ContractResult<()> {
+    let key = (amalgam, user, asset);
+    let mut position = positions().may_load(ctx.deps.storage, key)?.unwrap_or(Position {
+      amalgam,
+      user: user.clone(),
+      asset: asset.clone(),
+      deposited: Uint128::zero(),
+      minted: Uint128::zero(),
+    });
+
+    match action {
+      HistoryAction::Deposit => {
+        position.deposited += amount;
+        position.minted += minted;
+      }
+      // users may withdraw more of a component than they deposited, using Amalgam minted from other components
+      HistoryAction::Withdraw => {
+        position.deposited = position.deposited.saturating_sub(amount);
+        position.minted = position.minted.saturating_sub(minted);
+      }
+    }
+
+    if position.deposited.is_zero() && position.minted.is_zero() {
+      positions().remove(ctx.deps.storage, key)?;
+    } else {
+      positions().save(ctx.deps.storage, key, &position)?;
+    }
+    Ok(())
+  }
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
 
-  use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+  use amalgam_utils::ibc_hooks::{intermediate_sender, IbcLifecycleComplete, Memo, MsgTransferResponse};
+  use std::marker::PhantomData;
 
-  use crate::state::State;
+  use amalgam_utils::tokenfactory::osmosis::{MsgChangeAdmin, MsgSetDenomMetadata};
+  use amalgam_utils::tokenfactory::{DenomUnit, QueryDenomMetadataResponse};
+  use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info, MockQuerier};
+  use cosmwasm_std::{
+    to_json_binary, CosmosMsg, Decimal, Deps, Empty, OwnedDeps, Querier, QuerierResult, QuerierWrapper, QueryRequest,
+    Reply, Storage, SubMsgResponse, SubMsgResult, SystemResult, WasmQuery,
+  };
+  use prost::Message;
+
+  use crate::conversion::{ConversionFunction, ConversionSource, Ratio, SchedulePoint};
+  use crate::contract::reply;
+  use crate::msg::{
+    BalanceAtHeightResponse, ClaimsResponse, ComponentBacking, DonationsResponse, ExcessBackingResponse, QueryMsg, SudoMsg,
+    SupplyAtHeightResponse, TotalPowerAtHeightResponse, UserHistoryResponse, UserPositionResponse, VotingPowerAtHeightResponse,
+  };
+  use crate::query::query;
+  use crate::state::Refund;
+  use crate::sudo::sudo;
+
+  const AMALGAM: u64 = 1;
+  const DENOM: &str = "factory/cosmos2contract/amalgam";
+
+  /// Answers Stargate queries to `path` with `response`, and everything else with a `MockQuerier`.
+  struct StargateQuerier {
+    path: &'static str,
+    response: Vec<u8>,
+    base: MockQuerier,
+  }
+
+  impl StargateQuerier {
+    fn new(path: &'static str, response: Vec<u8>) -> Self {
+      Self { path, response, base: MockQuerier::default() }
+    }
+  }
+
+  impl Querier for StargateQuerier {
+    fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+      match from_json::<QueryRequest<Empty>>(bin_request) {
+        Ok(QueryRequest::Stargate { path, .. }) if path == self.path =>
+          SystemResult::Ok(cosmwasm_std::ContractResult::Ok(self.response.clone().into())),
+        _ => self.base.raw_query(bin_request),
+      }
+    }
+  }
+
+  /// Store Amalgam `AMALGAM` with the denom `DENOM`, administered by `admin`, after applying `update` to its state.
+  fn save_amalgam(storage: &mut dyn Storage, update: impl FnOnce(&mut State)) {
+    let mut state = State {
+      admin: "admin".to_string(),
+      fee_recipient: None,
+      track_history: false,
+      denom: DENOM.to_string(),
+    };
+    update(&mut state);
+    AMALGAMS.save(storage, AMALGAM, &state).unwrap();
+  }
+
+  fn add_component(component: Component) -> ExecuteMsg {
+    ExecuteMsg::AddComponent { amalgam: AMALGAM, component: component.into(), display_units: None }
+  }
 
   #[test]
   fn test_add_component_non_admin() {
@@ -256,15 +923,15 @@ mod tests {
     let env = mock_env();
     let info = mock_info("user", &[]);
 
-    let msg = ExecuteMsg::AddComponent(Component {
-      token: Asset::Native("uosmo".to_string()),
-      weight: Decimal::from_ratio(1u64, 100u64),
+    let msg = add_component(Component {
+      token: AssetInfo::Native("uosmo".to_string()),
+      conversion: ConversionFunction::Linear(Ratio::from_ratio(1u64, 100u64)),
       withdrawal_tax: 1000,
+      deposit_fee: None,
+      decimals: None,
     });
 
-    STATE.save(deps.as_mut().storage, &State {
-      admin: "admin".to_string(),
-    }).unwrap();
+    save_amalgam(deps.as_mut().storage, |_| {});
 
     let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
     assert!(matches!(res, Err(ContractError::Unauthorized)));
@@ -274,11 +941,738 @@ mod tests {
     let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
     assert!(matches!(res, Ok(_)));
 
-    let component = COMPONENTS.load(deps.as_mut().storage, "native:uosmo".to_string()).unwrap();
-    assert_eq!(component.weight, Decimal::from_ratio(1u64, 100u64));
+    let component = COMPONENTS.load(deps.as_mut().storage, (AMALGAM, &AssetInfo::Native("uosmo".to_string()))).unwrap();
+    assert_eq!(component.conversion, ConversionFunction::Linear(Ratio::from_ratio(1u64, 100u64)));
     assert_eq!(component.withdrawal_tax, 1000);
   }
 
+  #[test]
+  fn test_add_component_notifies_factory() {
+    let mut deps = mock_dependencies();
+    let env = mock_env();
+    let info = mock_info("admin", &[]);
+
+    save_amalgam(deps.as_mut().storage, |_| {});
+    let component = |denom: &str| add_component(Component {
+      token: AssetInfo::Native(denom.to_string()),
+      conversion: ConversionFunction::Linear(Ratio::one()),
+      withdrawal_tax: 0,
+      deposit_fee: None,
+      decimals: None,
+    });
+
+    // standalone contracts have no factory to notify
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), component("uosmo")).unwrap();
+    assert!(res.messages.is_empty());
+
+    FACTORY.save(deps.as_mut().storage, &Addr::unchecked("factory")).unwrap();
+    let res = execute(deps.as_mut(), env.clone(), info, component("uatom")).unwrap();
+    assert_eq!(res.messages[0].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+      contract_addr: "factory".to_string(),
+      msg: to_json_binary(&FactoryExecuteMsg::SyncInstance { address: env.contract.address.to_string() }).unwrap(),
+      funds: vec![],
+    }));
+  }
+
+  #[test]
+  fn test_create_amalgam_on_factory_instance() {
+    let mut deps = mock_dependencies();
+    FACTORY.save(deps.as_mut().storage, &Addr::unchecked("factory")).unwrap();
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("anyone", &[]), ExecuteMsg::CreateAmalgam {
+      subdenom: "amalgam".to_string(),
+      metadata: DenomMetadata::default(),
+      admin: "anyone".to_string(),
+      dao: None,
+      fee_recipient: None,
+      unstaking_duration: None,
+      track_history: None,
+      vote_escrow: None,
+    });
+    assert!(matches!(res, Err(ContractError::Unauthorized)));
+    assert!(AMALGAM_SEQ.may_load(deps.as_ref().storage).unwrap().is_none());
+  }
+
+  #[test]
+  fn test_add_component_validation() {
+    let mut deps = mock_dependencies();
+    let env = mock_env();
+    let info = mock_info("admin", &[]);
+
+    save_amalgam(deps.as_mut().storage, |_| {});
+
+    let component = |token: AssetInfoUnchecked| ExecuteMsg::AddComponent {
+      amalgam: AMALGAM,
+      component: Component {
+        token,
+        conversion: ConversionFunction::Linear(Ratio::one()),
+        withdrawal_tax: 0,
+        deposit_fee: None,
+        decimals: None,
+      },
+      display_units: None,
+    };
+
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), component(AssetInfoUnchecked::Native("u".to_string())));
+    assert!(matches!(res, Err(ContractError::Std(_))));
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), component(AssetInfoUnchecked::Native(DENOM.to_string())));
+    assert!(matches!(res, Err(ContractError::SelfComponent)));
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), component(AssetInfoUnchecked::Cw20("Token".to_string())));
+    assert!(matches!(res, Err(ContractError::Std(_))));
+
+    // not a cw20 contract
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), component(AssetInfoUnchecked::Cw20("token".to_string())));
+    assert!(matches!(res, Err(ContractError::Std(_))));
+
+    deps.querier.update_wasm(|query| {
+      let WasmQuery::Smart { contract_addr, .. } = query else { panic!("unexpected query") };
+      assert_eq!(contract_addr, "token");
+      let res = cw20::TokenInfoResponse {
+        name: "Token".to_string(),
+        symbol: "TKN".to_string(),
+        decimals: 6,
+        total_supply: Uint128::from(1000u64),
+      };
+      SystemResult::Ok(cosmwasm_std::ContractResult::Ok(to_json_binary(&res).unwrap()))
+    });
+    execute(deps.as_mut(), env.clone(), info.clone(), component(AssetInfoUnchecked::Cw20("token".to_string()))).unwrap();
+    let res = execute(deps.as_mut(), env, info, component(AssetInfoUnchecked::Cw20("token".to_string())));
+    assert!(matches!(res, Err(ContractError::DuplicateComponent)));
+
+    let component = COMPONENTS.load(deps.as_ref().storage, (AMALGAM, &AssetInfo::Cw20(Addr::unchecked("token")))).unwrap();
+    assert_eq!(component.token, AssetInfo::Cw20(Addr::unchecked("token")));
+  }
+
+  #[test]
+  fn test_add_component_display_units() {
+    let mut deps = mock_dependencies();
+    let env = mock_env();
+
+    save_amalgam(deps.as_mut().storage, |_| {});
+
+    let metadata = DenomMetadata {
+      base: DENOM.to_string(),
+      display: "amalgam".to_string(),
+      denom_units: vec![
+        DenomUnit { denom: DENOM.to_string(), exponent: 0, aliases: vec![] },
+        DenomUnit { denom: "amalgam".to_string(), exponent: 6, aliases: vec![] },
+      ],
+      ..Default::default()
+    };
+    let response = QueryDenomMetadataResponse { metadata: Some(metadata) }.encode_to_vec();
+    let mut deps = OwnedDeps {
+      storage: deps.storage,
+      api: deps.api,
+      querier: StargateQuerier::new("/cosmos.bank.v1beta1.Query/DenomMetadata", response),
+      custom_query_type: PhantomData,
+    };
+    deps.querier.base.update_wasm(|_| {
+      let res = cw20::TokenInfoResponse {
+        name: "Bridged LP".to_string(),
+        symbol: "BLP".to_string(),
+        decimals: 18,
+        total_supply: Uint128::from(1000u64),
+      };
+      SystemResult::Ok(cosmwasm_std::ContractResult::Ok(to_json_binary(&res).unwrap()))
+    });
+
+    // half an Amalgam per LP token, without hand-computing the 12 decimals in between
+    execute(deps.as_mut(), env.clone(), mock_info("admin", &[]), ExecuteMsg::AddComponent {
+      amalgam: AMALGAM,
+      component: Component {
+        token: AssetInfoUnchecked::Cw20("token".to_string()),
+        conversion: ConversionFunction::Linear(Ratio::percent(50)),
+        withdrawal_tax: 0,
+        deposit_fee: None,
+        decimals: None,
+      },
+      display_units: Some(true),
+    }).unwrap();
+
+    let component = COMPONENTS.load(deps.as_ref().storage, (AMALGAM, &AssetInfo::Cw20(Addr::unchecked("token")))).unwrap();
+    assert_eq!(component.decimals, Some(Decimals { component: 18, amalgam: 6 }));
+    assert_eq!(component.conversion, ConversionFunction::Linear(Ratio::percent(50)));
+
+    let querier = QuerierWrapper::new(&deps.querier);
+    let amount = Uint128::from(3_000_000_000_000_000_000u128);
+    assert_eq!(
+      component.simulate_deposit(&querier, &env, Uint128::zero(), amount).unwrap(),
+      (Uint128::from(1_500_000u64), Uint128::zero()),
+    );
+    assert_eq!(
+      component.simulate_withdraw(&querier, &env, amount, Uint128::from(1_500_000u64), Uint128::one()).unwrap(),
+      Uint128::from(2_000_000_000_000u64),
+    );
+
+    // rates of external sources are not scaled
+    let res = execute(deps.as_mut(), env, mock_info("admin", &[]), ExecuteMsg::AddComponent {
+      amalgam: AMALGAM,
+      component: Component {
+        token: AssetInfoUnchecked::Native("uosmo".to_string()),
+        conversion: ConversionFunction::Source(ConversionSource::Contract {
+          addr: "pricing".to_string(),
+          max_staleness: 60,
+          min_rate: Decimal::percent(50),
+          max_rate: Decimal::percent(200),
+        }),
+        withdrawal_tax: 0,
+        deposit_fee: None,
+        decimals: None,
+      },
+      display_units: Some(true),
+    });
+    assert!(matches!(res, Err(ContractError::DisplayUnitsNotSupported)));
+
+    // decimals cannot be provided instead of read
+    let res = execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), ExecuteMsg::AddComponent {
+      amalgam: AMALGAM,
+      component: Component {
+        token: AssetInfoUnchecked::Native("uosmo".to_string()),
+        conversion: ConversionFunction::Linear(Ratio::one()),
+        withdrawal_tax: 0,
+        deposit_fee: None,
+        decimals: Some(Decimals { component: 0, amalgam: 6 }),
+      },
+      display_units: None,
+    });
+    assert!(matches!(res, Err(ContractError::DecimalsNotAllowed)));
+  }
+
+  #[test]
+  fn test_deposit_fee() {
+    let mut deps = mock_dependencies();
+    let env = mock_env();
+    let info = mock_info("admin", &[]);
+
+    save_amalgam(deps.as_mut().storage, |state| state.fee_recipient = Some("treasury".to_string()));
+
+    let mut component = Component {
+      token: AssetInfo::Native("uosmo".to_string()),
+      conversion: ConversionFunction::Linear(Ratio::from_ratio(1u64, 2u64)),
+      withdrawal_tax: 0,
+      deposit_fee: Some(10001),
+      decimals: None,
+    };
+
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), add_component(component.clone()));
+    assert!(matches!(res, Err(ContractError::InvalidDepositFee)));
+
+    component.deposit_fee = Some(100);
+    execute(deps.as_mut(), env.clone(), info.clone(), add_component(component.clone())).unwrap();
+
+    assert_eq!(
+      component.simulate_deposit(&deps.as_ref().querier, &env, Uint128::zero(), Uint128::from(2000u64)).unwrap(),
+      (Uint128::from(990u64), Uint128::from(10u64)),
+    );
+
+    let info = mock_info("user", &[coin(2000, "uosmo")]);
+    let res = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Deposit { amalgam: AMALGAM, lock: None, recipient: None }).unwrap();
+    assert_eq!(res.messages.len(), 2);
+    assert!(res.attributes.iter().any(|attr| attr.key == "fee" && attr.value == "10"));
+
+    let balance = BALANCES.load(deps.as_mut().storage, (AMALGAM, &AssetInfo::Native("uosmo".to_string()))).unwrap();
+    assert_eq!(balance, Uint128::from(2000u64));
+  }
+
+  #[test]
+  fn test_schedule_withdraw() {
+    let mut deps = mock_dependencies();
+    let mut env = mock_env();
+    let info = mock_info("admin", &[]);
+
+    save_amalgam(deps.as_mut().storage, |_| {});
+
+    let start = env.block.time;
+    execute(deps.as_mut(), env.clone(), info, add_component(Component {
+      token: AssetInfo::Native("uosmo".to_string()),
+      conversion: ConversionFunction::Schedule(vec![
+        SchedulePoint { time: start, weight: Ratio::percent(200) },
+        SchedulePoint { time: start.plus_seconds(100), weight: Ratio::percent(100) },
+      ]),
+      withdrawal_tax: 0,
+      deposit_fee: None,
+      decimals: None,
+    })).unwrap();
+
+    // boosted deposit at launch
+    let info = mock_info("early", &[coin(1000, "uosmo")]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Deposit { amalgam: AMALGAM, lock: None, recipient: None }).unwrap();
+
+    // regular deposit after the boost decayed
+    env.block.time = start.plus_seconds(200);
+    let info = mock_info("late", &[coin(1000, "uosmo")]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Deposit { amalgam: AMALGAM, lock: None, recipient: None }).unwrap();
+
+    let minted = MINTED.load(deps.as_ref().storage, (AMALGAM, &AssetInfo::Native("uosmo".to_string()))).unwrap();
+    assert_eq!(minted, Uint128::from(3000u64));
+
+    // each position is released at the rate it was minted at
+    let denom = DENOM.to_string();
+    let info = mock_info("early", &[coin(2000, &denom)]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Withdraw { amalgam: AMALGAM, asset: AssetInfoUnchecked::Native("uosmo".to_string()), ibc: None }).unwrap();
+
+    let balance = BALANCES.load(deps.as_ref().storage, (AMALGAM, &AssetInfo::Native("uosmo".to_string()))).unwrap();
+    assert_eq!(balance, Uint128::from(1000u64));
+
+    let info = mock_info("late", &[coin(1001, &denom)]);
+    let res = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Withdraw { amalgam: AMALGAM, asset: AssetInfoUnchecked::Native("uosmo".to_string()), ibc: None });
+    assert!(matches!(res, Err(ContractError::InsufficientBalance)));
+
+    let info = mock_info("late", &[coin(1000, &denom)]);
+    execute(deps.as_mut(), env, info, ExecuteMsg::Withdraw { amalgam: AMALGAM, asset: AssetInfoUnchecked::Native("uosmo".to_string()), ibc: None }).unwrap();
+
+    let balance = BALANCES.load(deps.as_ref().storage, (AMALGAM, &AssetInfo::Native("uosmo".to_string()))).unwrap();
+    assert_eq!(balance, Uint128::zero());
+  }
+
+  #[test]
+  fn test_schedule_late_withdraw() {
+    let mut deps = mock_dependencies();
+    let mut env = mock_env();
+    let info = mock_info("admin", &[]);
+
+    save_amalgam(deps.as_mut().storage, |_| {});
+
+    let start = env.block.time;
+    execute(deps.as_mut(), env.clone(), info, add_component(Component {
+      token: AssetInfo::Native("uosmo".to_string()),
+      conversion: ConversionFunction::Schedule(vec![
+        SchedulePoint { time: start, weight: Ratio::percent(200) },
+        SchedulePoint { time: start.plus_seconds(100), weight: Ratio::percent(100) },
+      ]),
+      withdrawal_tax: 1000,
+      deposit_fee: None,
+      decimals: None,
+    })).unwrap();
+
+    let info = mock_info("early", &[coin(1000, "uosmo")]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Deposit { amalgam: AMALGAM, lock: None, recipient: None }).unwrap();
+
+    // a late depositor withdrawing right away gets the full deposit back, minus the tax
+    env.block.time = start.plus_seconds(200);
+    let info = mock_info("late", &[coin(1000, "uosmo")]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Deposit { amalgam: AMALGAM, lock: None, recipient: None }).unwrap();
+
+    let denom = DENOM.to_string();
+    let info = mock_info("late", &[coin(1000, &denom)]);
+    let res = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Withdraw { amalgam: AMALGAM, asset: AssetInfoUnchecked::Native("uosmo".to_string()), ibc: None }).unwrap();
+    assert_eq!(res.messages[1].msg, CosmosMsg::Bank(BankMsg::Send {
+      to_address: "late".to_string(),
+      amount: vec![coin(900, "uosmo")],
+    }));
+
+    // holders without a position in the component cannot withdraw it
+    let info = mock_info("other", &[coin(100, &denom)]);
+    let res = execute(deps.as_mut(), env, info, ExecuteMsg::Withdraw { amalgam: AMALGAM, asset: AssetInfoUnchecked::Native("uosmo".to_string()), ibc: None });
+    assert!(matches!(res, Err(ContractError::InsufficientBalance)));
+  }
+
+  #[test]
+  fn test_supply_and_balance_snapshots() {
+    let mut deps = mock_dependencies();
+    let mut env = mock_env();
+    let denom = DENOM.to_string();
+
+    save_amalgam(deps.as_mut().storage, |_| {});
+
+    let info = mock_info("admin", &[]);
+    execute(deps.as_mut(), env.clone(), info, add_component(Component {
+      token: AssetInfo::Native("uosmo".to_string()),
+      conversion: ConversionFunction::Linear(Ratio::percent(200)),
+      withdrawal_tax: 0,
+      deposit_fee: None,
+      decimals: None,
+    })).unwrap();
+
+    let start = env.block.height;
+    let info = mock_info("user", &[coin(1000, "uosmo")]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Deposit { amalgam: AMALGAM, lock: None, recipient: None }).unwrap();
+
+    env.block.height += 1;
+    let info = mock_info("user", &[coin(500, &denom)]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Withdraw { amalgam: AMALGAM, asset: AssetInfoUnchecked::Native("uosmo".to_string()), ibc: None }).unwrap();
+
+    let snapshot = |deps: Deps, height: u64| -> (Uint128, Uint128) {
+      let supply: SupplyAtHeightResponse = from_json(query(deps, mock_env(), QueryMsg::SupplyAtHeight {
+        amalgam: AMALGAM,
+        height: Some(height),
+      }).unwrap()).unwrap();
+      let balance: BalanceAtHeightResponse = from_json(query(deps, mock_env(), QueryMsg::BalanceAtHeight {
+        amalgam: AMALGAM,
+        asset: AssetInfoUnchecked::Native("uosmo".to_string()),
+        height: Some(height),
+      }).unwrap()).unwrap();
+      (supply.supply, balance.balance)
+    };
+
+    assert_eq!(snapshot(deps.as_ref(), start), (Uint128::zero(), Uint128::zero()));
+    assert_eq!(snapshot(deps.as_ref(), start + 1), (Uint128::from(2000u64), Uint128::from(1000u64)));
+    assert_eq!(snapshot(deps.as_ref(), start + 2), (Uint128::from(1500u64), Uint128::from(750u64)));
+  }
+
+  #[test]
+  fn test_user_position_and_history() {
+    let mut deps = mock_dependencies();
+    let env = mock_env();
+    let denom = DENOM.to_string();
+
+    save_amalgam(deps.as_mut().storage, |state| state.track_history = true);
+
+    for token in ["uosmo", "uatom"] {
+      let info = mock_info("admin", &[]);
+      execute(deps.as_mut(), env.clone(), info, add_component(Component {
+        token: AssetInfo::Native(token.to_string()),
+        conversion: ConversionFunction::Linear(Ratio::one()),
+        withdrawal_tax: 0,
+        deposit_fee: None,
+        decimals: None,
+      })).unwrap();
+
+      let info = mock_info("user", &[coin(1000, token)]);
+      execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Deposit { amalgam: AMALGAM, lock: None, recipient: None }).unwrap();
+    }
+
+    let info = mock_info("user", &[coin(400, &denom)]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Withdraw { amalgam: AMALGAM, asset: AssetInfoUnchecked::Native("uatom".to_string()), ibc: None }).unwrap();
+
+    let res: UserPositionResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::UserPosition {
+      amalgam: AMALGAM,
+      address: "user".to_string(),
+    }).unwrap()).unwrap();
+    assert_eq!(res.positions.len(), 2);
+    assert_eq!(res.positions[0].asset, AssetInfo::Native("uatom".to_string()));
+    assert_eq!(res.positions[0].deposited, Uint128::from(600u64));
+    assert_eq!(res.positions[1].asset, AssetInfo::Native("uosmo".to_string()));
+    assert_eq!(res.positions[1].deposited, Uint128::from(1000u64));
+
+    let res: UserHistoryResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::UserHistory {
+      amalgam: AMALGAM,
+      address: "user".to_string(),
+      start_after: Some(0),
+      limit: None,
+    }).unwrap()).unwrap();
+    assert_eq!(res.entries.len(), 2);
+    assert_eq!(res.entries[0].action, HistoryAction::Deposit);
+    assert_eq!(res.entries[1].action, HistoryAction::Withdraw);
+    assert_eq!(res.entries[1].amalgam, Uint128::from(400u64));
+  }
+
+  #[test]
+  fn test_stake_unstake_claim() {
+    let mut deps = mock_dependencies();
+    let mut env = mock_env();
+    let denom = DENOM.to_string();
+
+    save_amalgam(deps.as_mut().storage, |_| {});
+    STAKING.save(deps.as_mut().storage, AMALGAM, &StakingConfig {
+      dao: Addr::unchecked("dao"),
+      unstaking_duration: Some(Duration::Time(100)),
+    }).unwrap();
+
+    let info = mock_info("user", &[coin(1000, "uosmo")]);
+    let res = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake { amalgam: AMALGAM });
+    assert!(matches!(res, Err(ContractError::InvalidFunds)));
+
+    let info = mock_info("user", &[coin(1000, &denom)]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake { amalgam: AMALGAM }).unwrap();
+    let stake_height = env.block.height;
+
+    env.block.height += 1;
+    env.block.time = env.block.time.plus_seconds(5);
+
+    let info = mock_info("user", &[]);
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), ExecuteMsg::Unstake { amalgam: AMALGAM, amount: Uint128::from(1001u64) });
+    assert!(matches!(res, Err(ContractError::InsufficientStake)));
+
+    execute(deps.as_mut(), env.clone(), info.clone(), ExecuteMsg::Unstake { amalgam: AMALGAM, amount: Uint128::from(400u64) }).unwrap();
+
+    let power = |deps: Deps, height: u64| -> (Uint128, Uint128) {
+      let user: VotingPowerAtHeightResponse = from_json(query(deps, mock_env(), QueryMsg::VotingPowerAtHeight {
+        amalgam: Some(AMALGAM),
+        address: "user".to_string(),
+        height: Some(height),
+      }).unwrap()).unwrap();
+      let total: TotalPowerAtHeightResponse = from_json(query(deps, mock_env(), QueryMsg::TotalPowerAtHeight {
+        amalgam: Some(AMALGAM),
+        height: Some(height),
+      }).unwrap()).unwrap();
+      (user.power, total.power)
+    };
+
+    assert_eq!(power(deps.as_ref(), stake_height), (Uint128::zero(), Uint128::zero()));
+    assert_eq!(power(deps.as_ref(), stake_height + 1), (Uint128::from(1000u64), Uint128::from(1000u64)));
+    assert_eq!(power(deps.as_ref(), stake_height + 2), (Uint128::from(600u64), Uint128::from(600u64)));
+
+    // queries of the DAO DAO voting module interface, which has no Amalgam id, at the start of the current block
+    let msg = from_json(r#"{"voting_power_at_height":{"address":"user"}}"#).unwrap();
+    let user: VotingPowerAtHeightResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+    assert_eq!(user.power, Uint128::from(1000u64));
+    let msg = from_json(r#"{"total_power_at_height":{}}"#).unwrap();
+    let total: TotalPowerAtHeightResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+    assert_eq!(total.power, Uint128::from(1000u64));
+    let dao: Addr = from_json(query(deps.as_ref(), env.clone(), from_json(r#"{"dao":{}}"#).unwrap()).unwrap()).unwrap();
+    assert_eq!(dao, Addr::unchecked("dao"));
+
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), ExecuteMsg::Claim { amalgam: AMALGAM });
+    assert!(matches!(res, Err(ContractError::NothingToClaim)));
+
+    env.block.time = env.block.time.plus_seconds(100);
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), ExecuteMsg::Claim { amalgam: AMALGAM }).unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert!(CLAIMS.may_load(deps.as_ref().storage, (AMALGAM, &Addr::unchecked("user"))).unwrap().is_none());
+  }
+
+  #[test]
+  fn test_ibc_hook_deposit() {
+    let mut deps = mock_dependencies();
+    let env = mock_env();
+    let denom = "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2";
+
+    save_amalgam(deps.as_mut().storage, |_| {});
+    COMPONENTS.save(deps.as_mut().storage, (AMALGAM, &AssetInfo::Native(denom.to_string())), &Component {
+      token: AssetInfo::Native(denom.to_string()),
+      conversion: ConversionFunction::Linear(Ratio::one()),
+      withdrawal_tax: 0,
+      deposit_fee: None,
+      decimals: None,
+    }).unwrap();
+
+    // memo of the ICS20 transfer as sent from the source chain
+    let memo = format!(
+      r#"{{"wasm":{{"contract":"{}","msg":{{"deposit":{{"amalgam":1,"recipient":"osmo1recipient"}}}}}}}}"#,
+      env.contract.address,
+    );
+    let memo: Memo<ExecuteMsg> = from_json(memo.as_bytes()).unwrap();
+    assert_eq!(memo.wasm.contract, env.contract.address.to_string());
+
+    // ibc-hooks executes the contract from an intermediary address with the received voucher
+    let sender = intermediate_sender("channel-0", "cosmos1sender", "osmo").unwrap();
+    let info = mock_info(&sender, &[coin(1000, denom)]);
+
+    // without a recipient, the Amalgam would be minted to the intermediary address
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), ExecuteMsg::Deposit { amalgam: AMALGAM, lock: None, recipient: None });
+    assert!(matches!(res, Err(ContractError::MissingRecipient)));
+
+    let res = execute(deps.as_mut(), env.clone(), info, memo.wasm.msg).unwrap();
+    assert!(res.attributes.iter().any(|attr| attr.key == "recipient" && attr.value == "osmo1recipient"));
+
+    let position = |address: &str| positions()
+      .may_load(deps.as_ref().storage, (AMALGAM, &Addr::unchecked(address), &AssetInfo::Native(denom.to_string())))
+      .unwrap();
+    assert_eq!(position("osmo1recipient").unwrap().minted, Uint128::from(1000u64));
+    assert!(position(&sender).is_none());
+
+    // local deposits of the voucher still mint to the sender
+    let info = mock_info("user", &[coin(1000, denom)]);
+    execute(deps.as_mut(), env, info, ExecuteMsg::Deposit { amalgam: AMALGAM, lock: None, recipient: None }).unwrap();
+    let position = positions()
+      .load(deps.as_ref().storage, (AMALGAM, &Addr::unchecked("user"), &AssetInfo::Native(denom.to_string())))
+      .unwrap();
+    assert_eq!(position.minted, Uint128::from(1000u64));
+  }
+
+  #[test]
+  fn test_ibc_withdraw_refund() {
+    let mut deps = mock_dependencies();
+    let env = mock_env();
+    let denom = DENOM.to_string();
+
+    save_amalgam(deps.as_mut().storage, |_| {});
+    for asset in [AssetInfo::Native("uatom".to_string()), AssetInfo::Cw20(Addr::unchecked("token"))] {
+      COMPONENTS.save(deps.as_mut().storage, (AMALGAM, &asset), &Component {
+        token: asset.clone(),
+        conversion: ConversionFunction::Linear(Ratio::one()),
+        withdrawal_tax: 0,
+        deposit_fee: None,
+        decimals: None,
+      }).unwrap();
+      BALANCES.save(deps.as_mut().storage, (AMALGAM, &asset), &Uint128::from(1000u64), env.block.height).unwrap();
+    }
+    SUPPLY.save(deps.as_mut().storage, AMALGAM, &Uint128::from(2000u64), env.block.height).unwrap();
+
+    let destination = IbcDestination {
+      channel: "channel-0".to_string(),
+      receiver: "cosmos1receiver".to_string(),
+      timeout: 600,
+    };
+
+    let info = mock_info("user", &[coin(400, &denom)]);
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), ExecuteMsg::Withdraw {
+      amalgam: AMALGAM,
+      asset: AssetInfoUnchecked::Cw20("token".to_string()),
+      ibc: Some(destination.clone()),
+    });
+    assert!(matches!(res, Err(ContractError::IbcTransferNotSupported)));
+
+    let res = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Withdraw {
+      amalgam: AMALGAM,
+      asset: AssetInfoUnchecked::Native("uatom".to_string()),
+      ibc: Some(destination),
+    }).unwrap();
+
+    let transfer = res.messages.iter().find(|msg| msg.id == IBC_TRANSFER_REPLY_ID).unwrap();
+    let CosmosMsg::Stargate { type_url, value } = &transfer.msg else { panic!("expected MsgTransfer") };
+    assert_eq!(type_url, MsgTransfer::TYPE_URL);
+    let transfer = MsgTransfer::decode(value.as_slice()).unwrap();
+    assert_eq!(transfer.token.unwrap().amount, "400");
+    assert_eq!(transfer.timeout_timestamp, env.block.time.plus_seconds(600).nanos());
+    assert_eq!(transfer.memo, format!(r#"{{"ibc_callback":"{}"}}"#, env.contract.address));
+
+    reply(deps.as_mut(), env.clone(), Reply {
+      id: IBC_TRANSFER_REPLY_ID,
+      result: SubMsgResult::Ok(SubMsgResponse {
+        events: vec![],
+        data: Some(MsgTransferResponse { sequence: 7 }.encode_to_vec().into()),
+      }),
+    }).unwrap();
+
+    let res = sudo(deps.as_mut(), env.clone(), SudoMsg::IbcLifecycleComplete(IbcLifecycleComplete::IbcTimeout {
+      channel: "channel-0".to_string(),
+      sequence: 8,
+    }));
+    assert!(matches!(res, Err(ContractError::UnknownTransfer)));
+
+    sudo(deps.as_mut(), env.clone(), SudoMsg::IbcLifecycleComplete(IbcLifecycleComplete::IbcTimeout {
+      channel: "channel-0".to_string(),
+      sequence: 7,
+    })).unwrap();
+
+    let claims: ClaimsResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::Claims {
+      amalgam: AMALGAM,
+      address: "user".to_string(),
+    }).unwrap()).unwrap();
+    assert_eq!(claims.refunds, vec![Refund { asset: AssetInfo::Native("uatom".to_string()), amount: Uint128::from(400u64) }]);
+
+    let res = execute(deps.as_mut(), env.clone(), mock_info("user", &[]), ExecuteMsg::Claim { amalgam: AMALGAM }).unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert!(REFUNDS.is_empty(deps.as_ref().storage));
+  }
+
+  #[test]
+  fn test_split_locks() {
+    let uosmo = AssetInfo::Native("uosmo".to_string());
+    let setup = || {
+      let mut deps = mock_dependencies();
+      save_amalgam(deps.as_mut().storage, |_| {});
+      COMPONENTS.save(deps.as_mut().storage, (AMALGAM, &uosmo), &Component {
+        token: uosmo.clone(),
+        conversion: ConversionFunction::Sqrt(Ratio::from_ratio(100u64, 1u64)),
+        withdrawal_tax: 0,
+        deposit_fee: None,
+        decimals: None,
+      }).unwrap();
+      VOTE_ESCROW.save(deps.as_mut().storage, AMALGAM, &VoteEscrowConfig {
+        max_lock_duration: 100,
+        max_multiplier: Decimal::percent(200),
+      }).unwrap();
+      deps
+    };
+    let lock = ExecuteMsg::Deposit { amalgam: AMALGAM, lock: Some(Lock { duration: 100 }), recipient: None };
+    let value = |deps: Deps, address: &str| LOCKS.load(deps.storage, (AMALGAM, &Addr::unchecked(address))).unwrap().amalgam;
+    let env = mock_env();
+
+    let mut single = setup();
+    execute(single.as_mut(), env.clone(), mock_info("alice", &[coin(2000, "uosmo")]), lock.clone()).unwrap();
+    assert_eq!(value(single.as_ref(), "alice"), Uint128::from(4472u64));
+
+    // the second half is valued on top of the first, whether in the same lock or another
+    let mut split = setup();
+    execute(split.as_mut(), env.clone(), mock_info("alice", &[coin(1000, "uosmo")]), lock.clone()).unwrap();
+    execute(split.as_mut(), env.clone(), mock_info("alice", &[coin(1000, "uosmo")]), ExecuteMsg::IncreaseLock { amalgam: AMALGAM }).unwrap();
+    assert!(value(split.as_ref(), "alice") <= value(single.as_ref(), "alice"));
+
+    let mut split = setup();
+    execute(split.as_mut(), env.clone(), mock_info("alice", &[coin(1000, "uosmo")]), lock.clone()).unwrap();
+    execute(split.as_mut(), env.clone(), mock_info("bob", &[coin(1000, "uosmo")]), lock).unwrap();
+    assert!(value(split.as_ref(), "alice") + value(split.as_ref(), "bob") <= value(single.as_ref(), "alice"));
+    assert_eq!(LOCKED.load(split.as_ref().storage, (AMALGAM, &uosmo)).unwrap(), Uint128::from(2000u64));
+
+    // withdrawn locks release their share of the curve
+    let mut env = env;
+    env.block.height += 100;
+    execute(split.as_mut(), env, mock_info("bob", &[]), ExecuteMsg::WithdrawExpired { amalgam: AMALGAM }).unwrap();
+    assert_eq!(LOCKED.load(split.as_ref().storage, (AMALGAM, &uosmo)).unwrap(), Uint128::from(1000u64));
+  }
+
+  #[test]
+  fn test_vote_escrow() {
+    let mut deps = mock_dependencies();
+    let mut env = mock_env();
+    let start = env.block.height;
+
+    save_amalgam(deps.as_mut().storage, |_| {});
+    COMPONENTS.save(deps.as_mut().storage, (AMALGAM, &AssetInfo::Native("uosmo".to_string())), &Component {
+      token: AssetInfo::Native("uosmo".to_string()),
+      conversion: ConversionFunction::Linear(Ratio::one()),
+      withdrawal_tax: 0,
+      deposit_fee: None,
+      decimals: None,
+    }).unwrap();
+
+    let lock = |duration| ExecuteMsg::Deposit { amalgam: AMALGAM, lock: Some(Lock { duration }), recipient: None };
+    let info = mock_info("alice", &[coin(1000, "uosmo")]);
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), lock(100));
+    assert!(matches!(res, Err(ContractError::LockingDisabled)));
+
+    VOTE_ESCROW.save(deps.as_mut().storage, AMALGAM, &VoteEscrowConfig {
+      max_lock_duration: 100,
+      max_multiplier: Decimal::percent(200),
+    }).unwrap();
+
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), lock(101));
+    assert!(matches!(res, Err(ContractError::InvalidLockDuration)));
+
+    // alice: 1000 * 2 * 100 / 100, bob: 500 * 2 * 50 / 100
+    execute(deps.as_mut(), env.clone(), info.clone(), lock(100)).unwrap();
+    execute(deps.as_mut(), env.clone(), mock_info("bob", &[coin(500, "uosmo")]), lock(50)).unwrap();
+    assert!(BALANCES.may_load(deps.as_ref().storage, (AMALGAM, &AssetInfo::Native("uosmo".to_string()))).unwrap().is_none());
+    let locked = |deps: Deps, address: &str| LOCKS.load(deps.storage, (AMALGAM, &Addr::unchecked(address))).unwrap().assets;
+    assert_eq!(locked(deps.as_ref(), "alice")[0].amount, Uint128::from(1000u64));
+    assert_eq!(locked(deps.as_ref(), "bob")[0].amount, Uint128::from(500u64));
+
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), lock(100));
+    assert!(matches!(res, Err(ContractError::LockExists)));
+
+    let power = |deps: Deps, address: &str, height: u64| -> (Uint128, Uint128) {
+      let user: VotingPowerAtHeightResponse = from_json(query(deps, mock_env(), QueryMsg::VotingPowerAtHeight {
+        amalgam: Some(AMALGAM),
+        address: address.to_string(),
+        height: Some(height),
+      }).unwrap()).unwrap();
+      let total: TotalPowerAtHeightResponse = from_json(query(deps, mock_env(), QueryMsg::TotalPowerAtHeight {
+        amalgam: Some(AMALGAM),
+        height: Some(height),
+      }).unwrap()).unwrap();
+      (user.power, total.power)
+    };
+
+    assert_eq!(power(deps.as_ref(), "alice", start), (Uint128::zero(), Uint128::zero()));
+    assert_eq!(power(deps.as_ref(), "alice", start + 1), (Uint128::from(1980u64), Uint128::from(2470u64)));
+    assert_eq!(power(deps.as_ref(), "bob", start + 25), (Uint128::from(250u64), Uint128::from(1750u64)));
+    assert_eq!(power(deps.as_ref(), "bob", start + 50), (Uint128::zero(), Uint128::from(1000u64)));
+
+    // alice doubles her lock and extends it to the maximum halfway through
+    env.block.height = start + 50;
+    let res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), ExecuteMsg::ExtendLock { amalgam: AMALGAM, duration: 40 });
+    assert!(matches!(res, Err(ContractError::InvalidLockDuration)));
+    execute(deps.as_mut(), env.clone(), info.clone(), ExecuteMsg::IncreaseLock { amalgam: AMALGAM }).unwrap();
+    execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), ExecuteMsg::ExtendLock { amalgam: AMALGAM, duration: 100 }).unwrap();
+
+    assert_eq!(power(deps.as_ref(), "alice", start + 50), (Uint128::from(1000u64), Uint128::from(1000u64)));
+    assert_eq!(power(deps.as_ref(), "alice", start + 51), (Uint128::from(3960u64), Uint128::from(3960u64)));
+    assert_eq!(power(deps.as_ref(), "alice", start + 150), (Uint128::zero(), Uint128::zero()));
+
+    let res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), ExecuteMsg::WithdrawExpired { amalgam: AMALGAM });
+    assert!(matches!(res, Err(ContractError::LockNotExpired)));
+
+    let res = execute(deps.as_mut(), env.clone(), mock_info("bob", &[coin(500, "uosmo")]), ExecuteMsg::IncreaseLock { amalgam: AMALGAM });
+    assert!(matches!(res, Err(ContractError::LockExpired)));
+    let res = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), ExecuteMsg::WithdrawExpired { amalgam: AMALGAM }).unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert!(LOCKS.may_load(deps.as_ref().storage, (AMALGAM, &Addr::unchecked("bob"))).unwrap().is_none());
+    assert_eq!(locked(deps.as_ref(), "alice")[0].amount, Uint128::from(2000u64));
+  }
+
   #[test]
   fn test_change_admin() {
     let mut deps = mock_dependencies();
@@ -286,12 +1680,11 @@ mod tests {
     let info = mock_info("user", &[]);
 
     let msg = ExecuteMsg::UpdateAdmin {
+      amalgam: AMALGAM,
       admin: "new_admin".to_string(),
     };
 
-    STATE.save(deps.as_mut().storage, &State {
-      admin: "admin".to_string(),
-    }).unwrap();
+    save_amalgam(deps.as_mut().storage, |_| {});
 
     let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
     assert!(matches!(res, Err(ContractError::Unauthorized)));
@@ -300,31 +1693,81 @@ mod tests {
     let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
     assert!(matches!(res, Ok(_)));
 
-    let state = STATE.load(deps.as_mut().storage).unwrap();
+    let state = AMALGAMS.load(deps.as_mut().storage, AMALGAM).unwrap();
     assert_eq!(state.admin, "new_admin".to_string());
   }
 
+  #[test]
+  fn test_transfer_denom_admin() {
+    let mut deps = mock_dependencies();
+    let env = mock_env();
+    let msg = ExecuteMsg::TransferDenomAdmin { amalgam: AMALGAM, new_admin: "multisig".to_string() };
+
+    save_amalgam(deps.as_mut().storage, |_| {});
+
+    let res = execute(deps.as_mut(), env.clone(), mock_info("user", &[]), msg.clone());
+    assert!(matches!(res, Err(ContractError::Unauthorized)));
+
+    let res = execute(deps.as_mut(), env, mock_info("admin", &[]), msg).unwrap();
+    let CosmosMsg::Stargate { type_url, value } = &res.messages[0].msg else { panic!("expected MsgChangeAdmin") };
+    assert_eq!(type_url, MsgChangeAdmin::TYPE_URL);
+    assert_eq!(MsgChangeAdmin::decode(value.as_slice()).unwrap(), MsgChangeAdmin {
+      sender: "cosmos2contract".to_string(),
+      denom: DENOM.to_string(),
+      new_admin: "multisig".to_string(),
+    });
+  }
+
   #[test]
   fn test_update_metadata() {
     let mut deps = mock_dependencies();
     let env = mock_env();
     let info = mock_info("user", &[]);
 
-    let msg = ExecuteMsg::UpdateMetadata(UpdateMetadataMsg {
-      name: Some("new_name".to_string()),
-      description: Some("new_description".to_string()),
-      uri: Some("new_uri".to_string()),
-      uri_hash: Some("new_uri_hash".to_string()),
-    });
+    let msg = ExecuteMsg::UpdateMetadata {
+      amalgam: AMALGAM,
+      metadata: UpdateMetadataMsg {
+        name: Some("new_name".to_string()),
+        description: Some("new_description".to_string()),
+        uri: Some("new_uri".to_string()),
+        uri_hash: Some("new_uri_hash".to_string()),
+      },
+    };
 
-    STATE.save(deps.as_mut().storage, &State {
-      admin: "admin".to_string(),
-    }).unwrap();
+    save_amalgam(deps.as_mut().storage, |_| {});
 
     let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
     assert!(matches!(res, Err(ContractError::Unauthorized)));
 
-    // NOTE: cannot update metadata w/o an actual chain, bank module mock is insufficient
+    // the bank mock cannot answer Stargate queries, so serve the existing metadata directly
+    let existing = DenomMetadata {
+      description: "description".to_string(),
+      denom_units: vec![DenomUnit { denom: DENOM.to_string(), exponent: 0, aliases: vec![] }],
+      base: DENOM.to_string(),
+      display: DENOM.to_string(),
+      name: "name".to_string(),
+      symbol: "AMAL".to_string(),
+      uri: "".to_string(),
+      uri_hash: "".to_string(),
+    };
+    let response = QueryDenomMetadataResponse { metadata: Some(existing.clone()) }.encode_to_vec();
+    let mut deps = OwnedDeps {
+      storage: deps.storage,
+      api: deps.api,
+      querier: StargateQuerier::new("/cosmos.bank.v1beta1.Query/DenomMetadata", response),
+      custom_query_type: PhantomData,
+    };
+
+    let res = execute(deps.as_mut(), env, mock_info("admin", &[]), msg).unwrap();
+    let CosmosMsg::Stargate { type_url, value } = &res.messages[0].msg else { panic!("expected MsgSetDenomMetadata") };
+    assert_eq!(type_url, MsgSetDenomMetadata::TYPE_URL);
+    assert_eq!(MsgSetDenomMetadata::try_from(value).unwrap().metadata, DenomMetadata {
+      name: "new_name".to_string(),
+      description: "new_description".to_string(),
+      uri: "new_uri".to_string(),
+      uri_hash: "new_uri_hash".to_string(),
+      ..existing
+    });
   }
 
   #[test]
@@ -334,26 +1777,194 @@ mod tests {
     let info = mock_info("user", &[]);
 
     let msg = ExecuteMsg::CollectTaxes {
-      asset: Asset::Native("utest".to_string()),
+      amalgam: AMALGAM,
+      asset: AssetInfoUnchecked::Native("utest".to_string()),
     };
 
-    STATE.save(deps.as_mut().storage, &State {
-      admin: "admin".to_string(),
+    save_amalgam(deps.as_mut().storage, |_| {});
+    COMPONENTS.save(deps.as_mut().storage, (AMALGAM, &AssetInfo::Native("utest".to_string())), &Component {
+      token: AssetInfo::Native("utest".to_string()),
+      conversion: ConversionFunction::Linear(Ratio::one()),
+      withdrawal_tax: 1000,
+      deposit_fee: None,
+      decimals: None,
     }).unwrap();
+    BALANCES.save(deps.as_mut().storage, (AMALGAM, &AssetInfo::Native("utest".to_string())), &Uint128::from(1000u64), env.block.height).unwrap();
+    SUPPLY.save(deps.as_mut().storage, AMALGAM, &Uint128::from(1000u64), env.block.height).unwrap();
 
     let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
     assert!(matches!(res, Err(ContractError::Unauthorized)));
 
-    let info = mock_info("admin", &[]);
-    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
+    let admin = mock_info("admin", &[]);
+    let res = execute(deps.as_mut(), env.clone(), admin.clone(), msg.clone());
     assert!(matches!(res, Err(ContractError::NoTaxes)));
 
-    BALANCES.save(deps.as_mut().storage, "native:utest".to_string(), &Uint128::from(1000u64)).unwrap();
+    // 10% of the withdrawn 500 utest is retained as taxes
+    let info = mock_info("user", &[coin(500, DENOM)]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Withdraw {
+      amalgam: AMALGAM,
+      asset: AssetInfoUnchecked::Native("utest".to_string()),
+      ibc: None,
+    }).unwrap();
 
-    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
-    assert!(matches!(res, Ok(_)));
+    let res = execute(deps.as_mut(), env.clone(), admin.clone(), msg.clone()).unwrap();
+    assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+      to_address: "admin".to_string(),
+      amount: vec![coin(50, "utest")],
+    }));
+
+    let res = execute(deps.as_mut(), env, admin, msg);
+    assert!(matches!(res, Err(ContractError::NoTaxes)));
+  }
+
+  #[test]
+  fn test_donate() {
+    let mut deps = mock_dependencies();
+    let env = mock_env();
+    let utest = AssetInfo::Native("utest".to_string());
+    let token = AssetInfo::Cw20(Addr::unchecked("token"));
+
+    save_amalgam(deps.as_mut().storage, |_| {});
+    for (asset, conversion) in [
+      (&utest, ConversionFunction::Linear(Ratio::percent(50))),
+      (&token, ConversionFunction::Linear(Ratio::one())),
+    ] {
+      COMPONENTS.save(deps.as_mut().storage, (AMALGAM, asset), &Component {
+        token: asset.clone(),
+        conversion,
+        withdrawal_tax: 0,
+        deposit_fee: None,
+        decimals: None,
+      }).unwrap();
+    }
+
+    let info = mock_info("user", &[coin(2000, "utest")]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Deposit { amalgam: AMALGAM, lock: None, recipient: None }).unwrap();
+
+    let donate = ExecuteMsg::Donate { amalgam: AMALGAM };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("donor", &[]), donate.clone());
+    assert!(matches!(res, Err(ContractError::InvalidFunds)));
+    let res = execute(deps.as_mut(), env.clone(), mock_info("donor", &[coin(500, "uosmo")]), donate.clone());
+    assert!(matches!(res, Err(ContractError::UnknownAsset)));
+
+    let res = execute(deps.as_mut(), env.clone(), mock_info("donor", &[coin(500, "utest")]), donate).unwrap();
+    assert!(res.messages.is_empty());
+    execute(deps.as_mut(), env.clone(), mock_info("token", &[]), ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+      sender: "donor".to_string(),
+      amount: Uint128::from(300u64),
+      msg: to_json_binary(&Cw20ReceivePayload::Donate { amalgam: AMALGAM }).unwrap(),
+    })).unwrap();
+
+    // donations back the Amalgam without minting any
+    assert_eq!(BALANCES.load(deps.as_ref().storage, (AMALGAM, &utest)).unwrap(), Uint128::from(2500u64));
+    assert_eq!(MINTED.load(deps.as_ref().storage, (AMALGAM, &utest)).unwrap(), Uint128::from(1000u64));
+    assert_eq!(SUPPLY.load(deps.as_ref().storage, AMALGAM).unwrap(), Uint128::from(1000u64));
+
+    let res: DonationsResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::Donations {
+      amalgam: AMALGAM,
+      address: "donor".to_string(),
+    }).unwrap()).unwrap();
+    assert_eq!(res.donations, vec![Asset::new(token.clone(), 300u128), Asset::new(utest.clone(), 500u128)]);
+
+    let res: ExcessBackingResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::ExcessBacking {
+      amalgam: AMALGAM,
+    }).unwrap()).unwrap();
+    assert_eq!(res, ExcessBackingResponse {
+      supply: Uint128::from(1000u64),
+      excess_value: Uint128::from(550u64),
+      components: vec![
+        ComponentBacking {
+          asset: token.clone(),
+          balance: Uint128::from(300u64),
+          minted: Uint128::zero(),
+          excess: Uint128::from(300u64),
+          excess_value: Uint128::from(300u64),
+          deficit: Uint128::zero(),
+        },
+        ComponentBacking {
+          asset: utest.clone(),
+          balance: Uint128::from(2500u64),
+          minted: Uint128::from(1000u64),
+          excess: Uint128::from(500u64),
+          excess_value: Uint128::from(250u64),
+          deficit: Uint128::zero(),
+        },
+      ],
+    });
+
+    // shortfalls are reported rather than hidden, e.g. 400 Amalgam minted against 300 tokens at a rate since lowered
+    MINTED.save(deps.as_mut().storage, (AMALGAM, &token), &Uint128::from(400u64)).unwrap();
+    let res: ExcessBackingResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::ExcessBacking {
+      amalgam: AMALGAM,
+    }).unwrap()).unwrap();
+    assert_eq!(res.components[0].excess, Uint128::zero());
+    assert_eq!(res.components[0].deficit, Uint128::from(100u64));
+    assert_eq!(res.excess_value, Uint128::from(250u64));
+
+    // the donation stays in the contract after all Amalgam is burnt, and is not collectable as taxes
+    let info = mock_info("user", &[coin(1000, DENOM)]);
+    let res = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Withdraw {
+      amalgam: AMALGAM,
+      asset: utest.clone().into(),
+      ibc: None,
+    }).unwrap();
+    assert_eq!(res.messages[1].msg, CosmosMsg::Bank(BankMsg::Send {
+      to_address: "user".to_string(),
+      amount: vec![coin(2000, "utest")],
+    }));
+    assert_eq!(BALANCES.load(deps.as_ref().storage, (AMALGAM, &utest)).unwrap(), Uint128::from(500u64));
+    let res = execute(deps.as_mut(), env, mock_info("admin", &[]), ExecuteMsg::CollectTaxes {
+      amalgam: AMALGAM,
+      asset: utest.into(),
+    });
+    assert!(matches!(res, Err(ContractError::NoTaxes)));
+  }
+
+  #[test]
+  fn test_independent_amalgams() {
+    let mut deps = mock_dependencies();
+    let env = mock_env();
+
+    for (amalgam, admin) in [(1, "admin"), (2, "other_admin")] {
+      AMALGAMS.save(deps.as_mut().storage, amalgam, &State {
+        admin: admin.to_string(),
+        fee_recipient: None,
+        track_history: false,
+        denom: format!("factory/cosmos2contract/amalgam{}", amalgam),
+      }).unwrap();
+    }
+
+    let component = Component {
+      token: AssetInfo::Native("uosmo".to_string()),
+      conversion: ConversionFunction::Linear(Ratio::one()),
+      withdrawal_tax: 0,
+      deposit_fee: None,
+      decimals: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("admin", &[]), ExecuteMsg::AddComponent {
+      amalgam: 2,
+      component: component.clone().into(),
+      display_units: None,
+    });
+    assert!(matches!(res, Err(ContractError::Unauthorized)));
+    execute(deps.as_mut(), env.clone(), mock_info("admin", &[]), add_component(component)).unwrap();
+
+    // the component only belongs to the first Amalgam
+    let info = mock_info("user", &[coin(1000, "uosmo")]);
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), ExecuteMsg::Deposit { amalgam: 2, lock: None, recipient: None });
+    assert!(matches!(res, Err(ContractError::UnknownAsset)));
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Deposit { amalgam: AMALGAM, lock: None, recipient: None }).unwrap();
+
+    // Amalgam of one id cannot be burnt against another
+    let info = mock_info("user", &[coin(1000, "factory/cosmos2contract/amalgam1")]);
+    let res = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Withdraw {
+      amalgam: 2,
+      asset: AssetInfoUnchecked::Native("uosmo".to_string()),
+      ibc: None,
+    });
+    assert!(matches!(res, Err(ContractError::InvalidFunds)));
 
-    let balance = BALANCES.may_load(deps.as_mut().storage, "native:utest".to_string()).unwrap();
-    assert_eq!(balance, None);
+    let res = execute(deps.as_mut(), env, mock_info("user", &[]), ExecuteMsg::Stake { amalgam: 3 });
+    assert!(matches!(res, Err(ContractError::UnknownAmalgam)));
   }
 }
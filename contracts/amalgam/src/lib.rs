@@ -1,9 +1,12 @@
 pub mod contract;
+pub mod conversion;
 pub mod error;
 pub mod exec;
 pub mod msg;
 pub mod query;
 pub mod state;
+pub mod sudo;
+pub mod ve;
 
 pub use crate::error::ContractError;
 pub type ContractResult<T> = std::result::Result<T, ContractError>;
@@ -1,50 +1,218 @@
+use amalgam_utils::asset::{Asset, AssetInfo, AssetInfoUnchecked};
+use amalgam_utils::ibc_hooks::IbcLifecycleComplete;
 use amalgam_utils::tokenfactory::DenomMetadata;
 use cosmwasm_schema::{cw_serde, QueryResponses};
+use cosmwasm_std::Uint128;
+use cw_utils::Duration;
 
-use crate::state::{Asset, Component};
+use crate::state::{Claim, Component, HistoryEntry, Position, Refund, State};
+use crate::ve::{VeLock, VoteEscrowConfig};
+
+/// Amalgam queried by the voting module queries if none is given, so a contract holding a single Amalgam, like the
+/// instances of the factory, can be used as a DAO DAO voting module as is.
+pub const DEFAULT_AMALGAM: u64 = 1;
 
 #[cw_serde]
 pub struct InstantiateMsg {
-  /// Admin of the Amalgam contract. The only one who can add new tokens to the Amalgamation.
-  pub admin: String,
-  /// Metadata of the Amalgam token.
-  pub metadata: DenomMetadata,
+  /// Factory deploying the contract, notified with `SyncInstance` whenever a component is added so its registry stays
+  /// up to date.
+  pub factory: Option<String>,
+}
+
+/// Messages the contract sends to the factory that deployed it, matching the factory's `ExecuteMsg`.
+#[cw_serde]
+pub enum FactoryExecuteMsg {
+  SyncInstance {
+    address: String,
+  },
 }
 
+#[cw_serde]
+pub struct MigrateMsg {}
+
 #[cw_serde]
 pub enum ExecuteMsg {
+  /// Create a new Amalgam with the tokenfactory denom `factory/{contract}/{subdenom}`. The denom creation fee must be
+  /// sent along, any excess is refunded. Only the factory can create Amalgams on instances it deployed.
+  CreateAmalgam {
+    subdenom: String,
+    /// Metadata of the Amalgam token.
+    metadata: DenomMetadata,
+    /// Admin of the Amalgam. The only one who can add new tokens to the Amalgamation.
+    admin: String,
+    /// DAO the Amalgam acts as a voting module for. Defaults to the sender if unset.
+    dao: Option<String>,
+    /// Recipient of deposit fees. Defaults to the admin if unset.
+    fee_recipient: Option<String>,
+    /// Duration unstaked Amalgam is locked for before it can be claimed. Unstaked Amalgam is released immediately if
+    /// unset.
+    unstaking_duration: Option<Duration>,
+    /// Whether to record every deposit and withdrawal in an append-only log, queryable with `UserHistory`. Defaults to
+    /// false.
+    track_history: Option<bool>,
+    /// Vote-escrow configuration. Locking is disabled if unset.
+    vote_escrow: Option<VoteEscrowConfig>,
+  },
+
   /// Register a new component token to the Amalgamation.
-  AddComponent(Component),
+  AddComponent {
+    amalgam: u64,
+    component: Component<String>,
+    /// Whether the conversion function is expressed in display units rather than base units. The decimals of the
+    /// component are read from its bank metadata or cw20 `token_info`, those of the Amalgam from its bank metadata.
+    display_units: Option<bool>,
+  },
+
+  /// Deposit a native token to the Amalgamation. If `lock` is set, the token is locked for vote-escrow voting power
+  /// instead of minting Amalgam. The Amalgam or lock goes to `recipient`, or the sender if unset. Deposits through IBC
+  /// hooks must set a recipient, as their sender is an intermediary address nobody controls.
+  Deposit {
+    amalgam: u64,
+    lock: Option<Lock>,
+    recipient: Option<String>,
+  },
 
-  /// Deposit a native token to the Amalgamation.
-  Deposit {},
+  /// Donate the native token sent along to back the Amalgam without minting any. Donations are tracked per donor, and
+  /// cannot be withdrawn or collected as taxes.
+  Donate {
+    amalgam: u64,
+  },
 
-  /// Withdraw a token from the Amalgamation. There is a withdrawal fee configurable for each component.
+  /// Withdraw a token from the Amalgamation. There is a withdrawal fee configurable for each component. Native
+  /// components can be sent to another chain with `ibc`.
   Withdraw {
-    asset: Asset,
+    amalgam: u64,
+    asset: AssetInfoUnchecked,
+    ibc: Option<IbcDestination>,
   },
 
   /// Receive a cw20 token with payload.
   Receive(cw20::Cw20ReceiveMsg),
 
-  /// Collect balances for a given asset. Callable only by the admin.
+  /// Collect the withdrawal taxes accrued for a given asset. Callable only by the admin.
   CollectTaxes {
-    asset: Asset,
+    amalgam: u64,
+    asset: AssetInfoUnchecked,
   },
 
   /// Update the metadata of the Amalgam token.
-  UpdateMetadata(UpdateMetadataMsg),
+  UpdateMetadata {
+    amalgam: u64,
+    metadata: UpdateMetadataMsg,
+  },
 
-  /// Update the admin of the Amalgam contract.
+  /// Update the admin of the Amalgam.
   UpdateAdmin {
+    amalgam: u64,
     admin: String,
   },
+
+  /// Update the recipient of deposit fees. Unsetting it sends deposit fees to the admin.
+  UpdateFeeRecipient {
+    amalgam: u64,
+    fee_recipient: Option<String>,
+  },
+
+  /// Hand the tokenfactory admin rights over the Amalgam denom to `new_admin` in an emergency. The contract can no
+  /// longer mint or burn the Amalgam afterwards, while `new_admin` gains full control over its supply.
+  TransferDenomAdmin {
+    amalgam: u64,
+    new_admin: String,
+  },
+
+  /// Stake the Amalgam sent along for voting power.
+  Stake {
+    amalgam: u64,
+  },
+
+  /// Unstake Amalgam. It can be claimed after the unstaking duration.
+  Unstake {
+    amalgam: u64,
+    amount: Uint128,
+  },
+
+  /// Claim all unstaked Amalgam whose unstaking duration has passed, and all refunds of failed IBC withdrawals.
+  Claim {
+    amalgam: u64,
+  },
+
+  /// Add the native token sent along to the sender's active lock.
+  IncreaseLock {
+    amalgam: u64,
+  },
+
+  /// Extend the sender's active lock to expire `duration` blocks from now.
+  ExtendLock {
+    amalgam: u64,
+    duration: u64,
+  },
+
+  /// Withdraw all components of the sender's expired lock.
+  WithdrawExpired {
+    amalgam: u64,
+  },
 }
 
 #[cw_serde]
 pub enum Cw20ReceivePayload {
-  /// Deposit a cw20 token to the Amalgamation.
-  Deposit {},
+  /// Deposit a cw20 token to the Amalgamation, optionally locking it. The Amalgam or lock goes to `recipient`, or the
+  /// sender if unset.
+  Deposit {
+    amalgam: u64,
+    lock: Option<Lock>,
+    recipient: Option<String>,
+  },
+
+  /// Add the cw20 token to the sender's active lock.
+  IncreaseLock {
+    amalgam: u64,
+  },
+
+  /// Donate the cw20 token to back the Amalgam without minting any.
+  Donate {
+    amalgam: u64,
+  },
+}
+
+#[cw_serde]
+pub struct IbcDestination {
+  /// Local channel to transfer the component over.
+  pub channel: String,
+  /// Address of the recipient on the other chain.
+  pub receiver: String,
+  /// Timeout of the transfer in seconds from now.
+  pub timeout: u64,
+}
+
+#[cw_serde]
+pub enum SudoMsg {
+  /// Outcome of an IBC withdrawal, sent by ibc-hooks.
+  IbcLifecycleComplete(IbcLifecycleComplete),
+
+  /// Replace the admin of an Amalgam, e.g. if the admin key was lost or compromised.
+  UpdateAdmin {
+    amalgam: u64,
+    admin: String,
+  },
+
+  /// Suspend all user actions of all Amalgams. Admin actions remain available.
+  Pause {},
+
+  /// Resume user actions.
+  Unpause {},
+
+  /// Collect the taxes of `asset` accrued by an Amalgam to `recipient`.
+  CollectTaxes {
+    amalgam: u64,
+    asset: AssetInfoUnchecked,
+    recipient: String,
+  },
+}
+
+#[cw_serde]
+pub struct Lock {
+  /// Number of blocks to lock the deposit for.
+  pub duration: u64,
 }
 
 #[cw_serde]
@@ -58,11 +226,218 @@ pub struct UpdateMetadataMsg {
 #[cw_serde]
 #[derive(QueryResponses)]
 pub enum QueryMsg {
+  /// Configuration of an Amalgam.
+  #[returns(AmalgamResponse)]
+  Amalgam {
+    amalgam: u64,
+  },
+
+  /// All Amalgams, by ascending id.
+  #[returns(AmalgamsResponse)]
+  Amalgams {
+    start_after: Option<u64>,
+    limit: Option<u32>,
+  },
+
   #[returns(ComponentsResponse)]
-  Components {},
+  Components {
+    amalgam: u64,
+  },
+
+  /// Simulate depositing `amount` of `asset`, including the component's deposit fee.
+  #[returns(SimulateDepositResponse)]
+  SimulateDeposit {
+    amalgam: u64,
+    asset: AssetInfoUnchecked,
+    amount: Uint128,
+  },
+
+  /// Total supply of Amalgam at the start of `height`, or the current block if unset.
+  #[returns(SupplyAtHeightResponse)]
+  SupplyAtHeight {
+    amalgam: u64,
+    height: Option<u64>,
+  },
+
+  /// Tracked balance backing the Amalgam of `asset` at the start of `height`, or the current block if unset.
+  #[returns(BalanceAtHeightResponse)]
+  BalanceAtHeight {
+    amalgam: u64,
+    asset: AssetInfoUnchecked,
+    height: Option<u64>,
+  },
+
+  /// Net deposits of `address` per component.
+  #[returns(UserPositionResponse)]
+  UserPosition {
+    amalgam: u64,
+    address: String,
+  },
+
+  /// Deposits and withdrawals of `address`, if history tracking is enabled.
+  #[returns(UserHistoryResponse)]
+  UserHistory {
+    amalgam: u64,
+    address: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+  },
+
+  /// Staked Amalgam plus vote-escrow power of `address` at the start of `height`, or the current block if unset.
+  #[returns(VotingPowerAtHeightResponse)]
+  VotingPowerAtHeight {
+    /// Defaults to `DEFAULT_AMALGAM`.
+    amalgam: Option<u64>,
+    address: String,
+    height: Option<u64>,
+  },
+
+  /// Total staked Amalgam plus vote-escrow power at the start of `height`, or the current block if unset.
+  #[returns(TotalPowerAtHeightResponse)]
+  TotalPowerAtHeight {
+    /// Defaults to `DEFAULT_AMALGAM`.
+    amalgam: Option<u64>,
+    height: Option<u64>,
+  },
+
+  /// DAO the Amalgam acts as a voting module for.
+  #[returns(cosmwasm_std::Addr)]
+  Dao {
+    /// Defaults to `DEFAULT_AMALGAM`.
+    amalgam: Option<u64>,
+  },
+
+  #[returns(InfoResponse)]
+  Info {},
+
+  /// Pending claims of unstaked Amalgam and refunds of failed IBC withdrawals of `address`.
+  #[returns(ClaimsResponse)]
+  Claims {
+    amalgam: u64,
+    address: String,
+  },
+
+  /// Lock of `address` and its current vote-escrow power.
+  #[returns(LockResponse)]
+  Lock {
+    amalgam: u64,
+    address: String,
+  },
+
+  /// Total donations of `address` per component.
+  #[returns(DonationsResponse)]
+  Donations {
+    amalgam: u64,
+    address: String,
+  },
+
+  /// Backing of each component in excess of the Amalgam minted against it, e.g. from donations.
+  #[returns(ExcessBackingResponse)]
+  ExcessBacking {
+    amalgam: u64,
+  },
+}
+
+#[cw_serde]
+pub struct AmalgamResponse {
+  pub id: u64,
+  pub state: State,
+}
+
+#[cw_serde]
+pub struct AmalgamsResponse {
+  pub amalgams: Vec<AmalgamResponse>,
 }
 
 #[cw_serde]
 pub struct ComponentsResponse {
   pub components: Vec<Component>,
 }
+
+#[cw_serde]
+pub struct SimulateDepositResponse {
+  /// Amount of Amalgam minted to the depositor.
+  pub amount: Uint128,
+  /// Amount of Amalgam minted to the fee recipient.
+  pub fee: Uint128,
+}
+
+#[cw_serde]
+pub struct SupplyAtHeightResponse {
+  pub supply: Uint128,
+  pub height: u64,
+}
+
+#[cw_serde]
+pub struct BalanceAtHeightResponse {
+  pub balance: Uint128,
+  pub height: u64,
+}
+
+#[cw_serde]
+pub struct UserPositionResponse {
+  pub positions: Vec<Position>,
+}
+
+#[cw_serde]
+pub struct UserHistoryResponse {
+  pub entries: Vec<HistoryEntry>,
+}
+
+#[cw_serde]
+pub struct VotingPowerAtHeightResponse {
+  pub power: Uint128,
+  pub height: u64,
+}
+
+#[cw_serde]
+pub struct TotalPowerAtHeightResponse {
+  pub power: Uint128,
+  pub height: u64,
+}
+
+#[cw_serde]
+pub struct InfoResponse {
+  pub info: cw2::ContractVersion,
+}
+
+#[cw_serde]
+pub struct ClaimsResponse {
+  pub claims: Vec<Claim>,
+  pub refunds: Vec<Refund>,
+}
+
+#[cw_serde]
+pub struct LockResponse {
+  pub lock: Option<VeLock>,
+  pub power: Uint128,
+}
+
+#[cw_serde]
+pub struct DonationsResponse {
+  pub donations: Vec<Asset>,
+}
+
+#[cw_serde]
+pub struct ExcessBackingResponse {
+  /// Total supply of the Amalgam.
+  pub supply: Uint128,
+  /// Amalgam the excess backing of all components would mint if it was deposited now.
+  pub excess_value: Uint128,
+  pub components: Vec<ComponentBacking>,
+}
+
+#[cw_serde]
+pub struct ComponentBacking {
+  pub asset: AssetInfo,
+  /// Tracked balance of the component.
+  pub balance: Uint128,
+  /// Amalgam minted against the component, net of withdrawals.
+  pub minted: Uint128,
+  /// Part of the balance not needed to back `minted`.
+  pub excess: Uint128,
+  /// Amalgam `excess` would mint if it was deposited now, before deposit fees.
+  pub excess_value: Uint128,
+  /// Part of `minted` the balance cannot back at the current conversion, e.g. after an external rate dropped.
+  pub deficit: Uint128,
+}
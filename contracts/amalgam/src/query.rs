@@ -1,20 +1,191 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
-use cosmwasm_std::{to_json_binary, Binary, Deps, Env, Order, StdResult};
+use cosmwasm_std::{to_json_binary, Binary, Deps, Env, Order, StdError, StdResult, Uint128};
+use cw_storage_plus::Bound;
 
-use crate::{msg::{ComponentsResponse, QueryMsg}, state::COMPONENTS};
+use amalgam_utils::asset::{Asset, AssetInfoUnchecked};
+
+use crate::conversion::ConversionFunction;
+use crate::msg::{
+  AmalgamResponse, AmalgamsResponse, BalanceAtHeightResponse, ClaimsResponse, ComponentBacking, ComponentsResponse, DonationsResponse,
+  ExcessBackingResponse, InfoResponse, LockResponse, QueryMsg, SimulateDepositResponse, DEFAULT_AMALGAM,
+  SupplyAtHeightResponse, TotalPowerAtHeightResponse, UserHistoryResponse, UserPositionResponse,
+  VotingPowerAtHeightResponse,
+};
+use crate::state::{
+  positions, AMALGAMS, BALANCES, CLAIMS, COMPONENTS, DONATIONS, HISTORY, LOCKS, MINTED, REFUNDS, STAKED_BALANCES, STAKED_TOTAL,
+  STAKING, SUPPLY, VOTE_ESCROW,
+};
+use crate::ve;
+
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 30;
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
   match msg {
-    QueryMsg::Components {} => to_json_binary(&query_components(deps)?),
+    QueryMsg::Amalgam { amalgam } => to_json_binary(&query_amalgam(deps, amalgam)?),
+    QueryMsg::Amalgams { start_after, limit } => to_json_binary(&query_amalgams(deps, start_after, limit)?),
+    QueryMsg::Components { amalgam } => to_json_binary(&query_components(deps, amalgam)?),
+    QueryMsg::SimulateDeposit { amalgam, asset, amount } => to_json_binary(&query_simulate_deposit(deps, env, amalgam, asset, amount)?),
+    QueryMsg::SupplyAtHeight { amalgam, height } => to_json_binary(&query_supply_at_height(deps, env, amalgam, height)?),
+    QueryMsg::BalanceAtHeight { amalgam, asset, height } => to_json_binary(&query_balance_at_height(deps, env, amalgam, asset, height)?),
+    QueryMsg::UserPosition { amalgam, address } => to_json_binary(&query_user_position(deps, amalgam, address)?),
+    QueryMsg::UserHistory { amalgam, address, start_after, limit } => to_json_binary(&query_user_history(deps, amalgam, address, start_after, limit)?),
+    QueryMsg::VotingPowerAtHeight { amalgam, address, height } => to_json_binary(&query_voting_power_at_height(deps, env, amalgam.unwrap_or(DEFAULT_AMALGAM), address, height)?),
+    QueryMsg::TotalPowerAtHeight { amalgam, height } => to_json_binary(&query_total_power_at_height(deps, env, amalgam.unwrap_or(DEFAULT_AMALGAM), height)?),
+    QueryMsg::Dao { amalgam } => to_json_binary(&STAKING.load(deps.storage, amalgam.unwrap_or(DEFAULT_AMALGAM))?.dao),
+    QueryMsg::Info {} => to_json_binary(&InfoResponse { info: cw2::get_contract_version(deps.storage)? }),
+    QueryMsg::Claims { amalgam, address } => to_json_binary(&query_claims(deps, amalgam, address)?),
+    QueryMsg::Lock { amalgam, address } => to_json_binary(&query_lock(deps, env, amalgam, address)?),
+    QueryMsg::Donations { amalgam, address } => to_json_binary(&query_donations(deps, amalgam, address)?),
+    QueryMsg::ExcessBacking { amalgam } => to_json_binary(&query_excess_backing(deps, env, amalgam)?),
   }
 }
 
-fn query_components(deps: Deps) -> StdResult<ComponentsResponse> {
+fn query_amalgam(deps: Deps, amalgam: u64) -> StdResult<AmalgamResponse> {
+  let state = AMALGAMS.load(deps.storage, amalgam)?;
+  Ok(AmalgamResponse { id: amalgam, state })
+}
+
+fn query_amalgams(deps: Deps, start_after: Option<u64>, limit: Option<u32>) -> StdResult<AmalgamsResponse> {
+  let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+  let amalgams = AMALGAMS
+    .range(deps.storage, start_after.map(Bound::exclusive), None, Order::Ascending)
+    .take(limit)
+    .map(|item| item.map(|(id, state)| AmalgamResponse { id, state }))
+    .collect::<StdResult<_>>()?;
+  Ok(AmalgamsResponse { amalgams })
+}
+
+fn query_components(deps: Deps, amalgam: u64) -> StdResult<ComponentsResponse> {
   let components = COMPONENTS
+    .prefix(amalgam)
     .range(deps.storage, None, None, Order::Ascending)
     .map(|item| item.map(|(_, component)| component).unwrap())
     .collect();
   Ok(ComponentsResponse { components })
 }
+
+fn query_simulate_deposit(deps: Deps, env: Env, amalgam: u64, asset: AssetInfoUnchecked, amount: Uint128) -> StdResult<SimulateDepositResponse> {
+  let asset = asset.check(deps.api)?;
+  let component = COMPONENTS.may_load(deps.storage, (amalgam, &asset))?
+    .ok_or_else(|| StdError::generic_err("Unknown asset"))?;
+  let balance = BALANCES.may_load(deps.storage, (amalgam, &asset))?.unwrap_or_default();
+  let (amount, fee) = component.simulate_deposit(&deps.querier, &env, balance, amount)
+    .map_err(|err| StdError::generic_err(err.to_string()))?;
+  Ok(SimulateDepositResponse { amount, fee })
+}
+
+fn query_supply_at_height(deps: Deps, env: Env, amalgam: u64, height: Option<u64>) -> StdResult<SupplyAtHeightResponse> {
+  let height = height.unwrap_or(env.block.height);
+  let supply = SUPPLY.may_load_at_height(deps.storage, amalgam, height)?.unwrap_or_default();
+  Ok(SupplyAtHeightResponse { supply, height })
+}
+
+fn query_balance_at_height(deps: Deps, env: Env, amalgam: u64, asset: AssetInfoUnchecked, height: Option<u64>) -> StdResult<BalanceAtHeightResponse> {
+  let asset = asset.check(deps.api)?;
+  let height = height.unwrap_or(env.block.height);
+  let balance = BALANCES.may_load_at_height(deps.storage, (amalgam, &asset), height)?.unwrap_or_default();
+  Ok(BalanceAtHeightResponse { balance, height })
+}
+
+fn query_user_position(deps: Deps, amalgam: u64, address: String) -> StdResult<UserPositionResponse> {
+  let address = deps.api.addr_validate(&address)?;
+  let positions = positions()
+    .prefix((amalgam, &address))
+    .range(deps.storage, None, None, Order::Ascending)
+    .map(|item| item.map(|(_, position)| position))
+    .collect::<StdResult<_>>()?;
+  Ok(UserPositionResponse { positions })
+}
+
+fn query_user_history(deps: Deps, amalgam: u64, address: String, start_after: Option<u64>, limit: Option<u32>) -> StdResult<UserHistoryResponse> {
+  let address = deps.api.addr_validate(&address)?;
+  let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+  let entries = HISTORY
+    .prefix((amalgam, &address))
+    .range(deps.storage, start_after.map(Bound::exclusive), None, Order::Ascending)
+    .take(limit)
+    .map(|item| item.map(|(_, entry)| entry))
+    .collect::<StdResult<_>>()?;
+  Ok(UserHistoryResponse { entries })
+}
+
+fn query_voting_power_at_height(deps: Deps, env: Env, amalgam: u64, address: String, height: Option<u64>) -> StdResult<VotingPowerAtHeightResponse> {
+  let address = deps.api.addr_validate(&address)?;
+  let height = height.unwrap_or(env.block.height);
+  let staked = STAKED_BALANCES.may_load_at_height(deps.storage, (amalgam, &address), height)?.unwrap_or_default();
+  let lock = LOCKS.may_load_at_height(deps.storage, (amalgam, &address), height)?;
+  let locked = match (VOTE_ESCROW.may_load(deps.storage, amalgam)?, lock) {
+    (Some(config), Some(lock)) => lock.power_at(&config, height)?,
+    _ => Uint128::zero(),
+  };
+  Ok(VotingPowerAtHeightResponse { power: staked + locked, height })
+}
+
+fn query_total_power_at_height(deps: Deps, env: Env, amalgam: u64, height: Option<u64>) -> StdResult<TotalPowerAtHeightResponse> {
+  let height = height.unwrap_or(env.block.height);
+  let staked = STAKED_TOTAL.may_load_at_height(deps.storage, amalgam, height)?.unwrap_or_default();
+  let locked = ve::total_power_at(deps.storage, amalgam, height)?;
+  Ok(TotalPowerAtHeightResponse { power: staked + locked, height })
+}
+
+fn query_claims(deps: Deps, amalgam: u64, address: String) -> StdResult<ClaimsResponse> {
+  let address = deps.api.addr_validate(&address)?;
+  let claims = CLAIMS.may_load(deps.storage, (amalgam, &address))?.unwrap_or_default();
+  let refunds = REFUNDS
+    .prefix((amalgam, &address))
+    .range(deps.storage, None, None, Order::Ascending)
+    .map(|item| item.map(|(_, refund)| refund))
+    .collect::<StdResult<_>>()?;
+  Ok(ClaimsResponse { claims, refunds })
+}
+
+fn query_lock(deps: Deps, env: Env, amalgam: u64, address: String) -> StdResult<LockResponse> {
+  let address = deps.api.addr_validate(&address)?;
+  let lock = LOCKS.may_load(deps.storage, (amalgam, &address))?;
+  let power = match (VOTE_ESCROW.may_load(deps.storage, amalgam)?, &lock) {
+    (Some(config), Some(lock)) => lock.power_at(&config, env.block.height)?,
+    _ => Uint128::zero(),
+  };
+  Ok(LockResponse { lock, power })
+}
+
+fn query_donations(deps: Deps, amalgam: u64, address: String) -> StdResult<DonationsResponse> {
+  let address = deps.api.addr_validate(&address)?;
+  let donations = DONATIONS
+    .prefix((amalgam, &address))
+    .range(deps.storage, None, None, Order::Ascending)
+    .map(|item| item.map(|(info, amount)| Asset::new(info, amount)))
+    .collect::<StdResult<_>>()?;
+  Ok(DonationsResponse { donations })
+}
+
+fn query_excess_backing(deps: Deps, env: Env, amalgam: u64) -> StdResult<ExcessBackingResponse> {
+  let mut components = vec![];
+  for item in COMPONENTS.prefix(amalgam).range(deps.storage, None, None, Order::Ascending) {
+    let (asset, component) = item?;
+    let balance = BALANCES.may_load(deps.storage, (amalgam, &asset))?.unwrap_or_default();
+    let minted = MINTED.may_load(deps.storage, (amalgam, &asset))?.unwrap_or_default();
+    let (excess, deficit) = match &component.conversion {
+      // positions of schedule components are released at the rate they were minted at, so only their deposits are owed
+      ConversionFunction::Schedule(_) => {
+        let owed = positions().idx.asset.prefix((amalgam, asset.clone()))
+          .range(deps.storage, None, None, Order::Ascending)
+          .map(|item| item.map(|(_, position)| position.deposited))
+          .sum::<StdResult<Uint128>>()?;
+        (balance.saturating_sub(owed), Uint128::zero())
+      }
+      _ => component.excess_backing(&deps.querier, &env, balance, minted)
+        .map_err(|err| StdError::generic_err(err.to_string()))?,
+    };
+    let (amount, fee) = component.simulate_deposit(&deps.querier, &env, balance - excess, excess)
+      .map_err(|err| StdError::generic_err(err.to_string()))?;
+    components.push(ComponentBacking { asset, balance, minted, excess, excess_value: amount + fee, deficit });
+  }
+
+  let supply = SUPPLY.may_load(deps.storage, amalgam)?.unwrap_or_default();
+  let excess_value = components.iter().map(|component| component.excess_value).sum();
+  Ok(ExcessBackingResponse { supply, excess_value, components })
+}
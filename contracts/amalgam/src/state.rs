@@ -1,68 +1,287 @@
-use cosmwasm_std::{coin, to_json_binary, Addr, BankMsg, CosmosMsg, Decimal, Deps, QuerierWrapper, Uint128, WasmMsg};
+use cosmwasm_std::{Addr, Api, Decimal256, Env, QuerierWrapper, Timestamp, Uint128};
 use cosmwasm_schema::cw_serde;
-use cw20::{Cw20Contract, Cw20ExecuteMsg};
-use cw_storage_plus::{Item, Map};
+use cw_storage_plus::{Index, IndexList, IndexedMap, Item, Map, MultiIndex, SnapshotMap, Strategy};
+use cw_utils::{Duration, Expiration};
 
+use amalgam_utils::asset::{AssetInfo, AssetInfoBase, AssetInfoUnchecked};
+
+use crate::conversion::{ConversionFunction, Decimals, Ratio};
+use crate::ve::{VeLock, VePoint, VoteEscrowConfig};
+use crate::{ContractError, ContractResult};
+
+/// Configuration of a single Amalgam.
 #[cw_serde]
 pub struct State {
-  /// Admin of the Amalgam contract. The only one who can add new tokens to the Amalgamation.
+  /// Admin of the Amalgam. The only one who can add new tokens to the Amalgamation.
   pub admin: String,
+  /// Recipient of deposit fees. Defaults to the admin if unset.
+  pub fee_recipient: Option<String>,
+  /// Whether to record every deposit and withdrawal in the append-only `HISTORY` log.
+  pub track_history: bool,
+  /// Denom of the Amalgam token, as reported by tokenfactory on creation.
+  pub denom: String,
+}
+
+#[cw_serde]
+pub struct StakingConfig {
+  /// DAO this Amalgam acts as a voting module for, i.e. its creator.
+  pub dao: Addr,
+  /// Duration unstaked Amalgam is locked for before it can be claimed. Unstaked Amalgam is released immediately if
+  /// unset.
+  pub unstaking_duration: Option<Duration>,
+}
+
+#[cw_serde]
+pub struct Claim {
+  pub amount: Uint128,
+  pub release_at: Expiration,
+}
+
+/// Component withdrawn to another chain, awaiting the outcome of the transfer.
+#[cw_serde]
+pub struct IbcTransfer {
+  pub amalgam: u64,
+  pub sender: Addr,
+  pub asset: AssetInfo,
+  pub amount: Uint128,
+  pub channel: String,
+}
+
+/// Component returned by a failed IBC withdrawal, claimable by the withdrawer.
+#[cw_serde]
+pub struct Refund {
+  pub asset: AssetInfo,
+  pub amount: Uint128,
+}
+
+/// Net deposits of a single user in a single component.
+#[cw_serde]
+pub struct Position {
+  pub amalgam: u64,
+  pub user: Addr,
+  pub asset: AssetInfo,
+  /// Net amount of the component deposited.
+  pub deposited: Uint128,
+  /// Net amount of Amalgam minted for the deposits.
+  pub minted: Uint128,
+}
+
+pub struct PositionIndexes<'a> {
+  /// Positions by Amalgam and asset, to list all depositors of a component.
+  pub asset: MultiIndex<'a, (u64, AssetInfo), Position, (u64, Addr, AssetInfo)>,
+}
+
+impl<'a> IndexList<Position> for PositionIndexes<'a> {
+  fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Position>> + '_> {
+    let v: Vec<&dyn Index<Position>> = vec![&self.asset];
+    Box::new(v.into_iter())
+  }
 }
 
 #[cw_serde]
-pub struct Component {
-  pub token: Asset,
-  pub weight: Decimal,
+pub enum HistoryAction {
+  Deposit,
+  Withdraw,
+}
+
+/// Entry of the append-only action log.
+#[cw_serde]
+pub struct HistoryEntry {
+  pub id: u64,
+  pub action: HistoryAction,
+  pub asset: AssetInfo,
+  /// Amount of the component deposited or withdrawn, before taxes.
+  pub amount: Uint128,
+  /// Amount of Amalgam minted or burnt.
+  pub amalgam: Uint128,
+  pub height: u64,
+  pub time: Timestamp,
+}
+
+/// Component of an Amalgam, with the address of a cw20 `token` of type `T`.
+#[cw_serde]
+pub struct Component<T = Addr> {
+  pub token: AssetInfoBase<T>,
+  /// How deposits of this component convert into Amalgam.
+  pub conversion: ConversionFunction,
   /// In basis points.
   pub withdrawal_tax: u16,
+  /// In basis points. Share of the minted Amalgam that goes to the fee recipient instead of the depositor.
+  pub deposit_fee: Option<u16>,
+  /// Decimals of the component and the Amalgam if the conversion is expressed in display units, or `None` if it is
+  /// expressed in base units. Read by the contract when the component is added with `display_units`, and must be
+  /// `None` in `ExecuteMsg::AddComponent`.
+  pub decimals: Option<Decimals>,
 }
 
-#[cw_serde]
-pub enum Asset {
-  Native(String),
-  Cw20(String),
+impl Component<String> {
+  /// Validate the token, as received in `ExecuteMsg::AddComponent`. The decimals are read by the contract and must not
+  /// be set.
+  pub fn check(self, api: &dyn Api) -> ContractResult<Component> {
+    if self.decimals.is_some() {
+      return Err(ContractError::DecimalsNotAllowed);
+    }
+    Ok(Component {
+      token: self.token.check(api)?,
+      conversion: self.conversion,
+      withdrawal_tax: self.withdrawal_tax,
+      deposit_fee: self.deposit_fee,
+      decimals: None,
+    })
+  }
+}
+
+impl From<Component> for Component<String> {
+  fn from(component: Component) -> Self {
+    Component {
+      token: AssetInfoUnchecked::from(component.token),
+      conversion: component.conversion,
+      withdrawal_tax: component.withdrawal_tax,
+      deposit_fee: component.deposit_fee,
+      decimals: component.decimals,
+    }
+  }
 }
 
-impl Asset {
-  pub fn key(&self) -> String {
-    match self {
-      Asset::Native(denom) => format!("native:{}", denom),
-      Asset::Cw20(contract) => format!("cw20:{}", contract),
+impl Component {
+  /// Compute the Amalgam minted for a deposit of `amount` on top of the tracked `balance`, split into the depositor's
+  /// share and the deposit fee.
+  pub fn simulate_deposit(&self, querier: &QuerierWrapper, env: &Env, balance: Uint128, amount: Uint128) -> ContractResult<(Uint128, Uint128)> {
+    let gross = self.resolve(querier, env)?.deposit(balance, amount)?;
+    // fees round up, in favor of the protocol
+    let fee = Ratio::from_ratio(self.deposit_fee.unwrap_or(0) as u64, 10000u64).mul_ceil(gross)?;
+    Ok((gross - fee, fee))
+  }
+
+  /// Compute the amount of component released before taxes when burning `amount` Amalgam against the tracked
+  /// `balance`, of which `minted` Amalgam was issued. Schedule components are released per position instead, so for
+  /// them `balance` and `minted` are the amounts deposited and minted by the withdrawer's position.
+  pub fn simulate_withdraw(&self, querier: &QuerierWrapper, env: &Env, balance: Uint128, minted: Uint128, amount: Uint128) -> ContractResult<Uint128> {
+    match &self.conversion {
+      // weights change over time, so each position is released at the rate it was minted at, and a late deposit never
+      // funds the boost of an earlier one
+      ConversionFunction::Schedule(_) => {
+        if amount > minted {
+          return Err(ContractError::InsufficientBalance);
+        }
+        if amount.is_zero() {
+          return Ok(Uint128::zero());
+        }
+        Ok(balance.multiply_ratio(amount, minted))
+      }
+      _ => self.resolve(querier, env)?.withdraw(balance, amount),
     }
   }
 
-  pub fn send(&self, amount: Uint128, recipient: String) -> CosmosMsg {
-    match self {
-      Asset::Native(denom) =>
-        BankMsg::Send { to_address: recipient, amount: vec![coin(amount.into(), denom.clone())] }.into(),
-      Asset::Cw20(contract) =>
-        WasmMsg::Execute {
-          contract_addr: contract.clone(),
-          msg: to_json_binary(&Cw20ExecuteMsg::Transfer {
-            recipient: recipient.clone(),
-            amount: amount.into(),
-          }).unwrap(),
-          funds: vec![],
-        }.into(),
+  /// Compute the part of the tracked `balance` that is not needed to back the `minted` Amalgam, e.g. from donations,
+  /// and the part of `minted` the balance cannot back, e.g. after an external rate dropped.
+  pub fn excess_backing(&self, querier: &QuerierWrapper, env: &Env, balance: Uint128, minted: Uint128) -> ContractResult<(Uint128, Uint128)> {
+    if minted.is_zero() {
+      return Ok((balance, Uint128::zero()));
+    }
+    match self.simulate_withdraw(querier, env, balance, minted, minted) {
+      Ok(released) => Ok((balance - released, Uint128::zero())),
+      Err(ContractError::InsufficientBalance) => {
+        let backed = self.resolve(querier, env)?.deposit(Uint128::zero(), balance)?;
+        Ok((Uint128::zero(), minted.saturating_sub(backed)))
+      }
+      Err(err) => Err(err),
     }
   }
 
-  pub fn balance(&self, deps: &Deps, querier: &QuerierWrapper, address: Addr) -> Uint128 {
-    match self {
-      Asset::Native(denom) => {
-        let balance = querier.query_balance(address, denom);
-        balance.map_or(Uint128::zero(), |balance| balance.amount)
-      },
-      Asset::Cw20(contract) => {
-        let contract = deps.api.addr_validate(contract).unwrap();
-        let contract = Cw20Contract(contract);
-        contract.balance(querier, address).unwrap_or(Uint128::zero())
-      },
+  /// Resolve the conversion function for the current block, in base units.
+  fn resolve(&self, querier: &QuerierWrapper, env: &Env) -> ContractResult<ConversionFunction> {
+    let conversion = self.conversion.resolve(querier, env, &self.token)?;
+    match &self.decimals {
+      Some(decimals) => Ok(conversion.to_base_units(decimals)?),
+      None => Ok(conversion),
     }
   }
 }
 
-pub const STATE: Item<State> = Item::new("state");
-pub const COMPONENTS: Map<String, Component> = Map::new("components");
-/// Map of asset keys to balances.
-pub const BALANCES: Map<String, Uint128> = Map::new("balances");
+/// Map of Amalgam ids to their configuration.
+pub const AMALGAMS: Map<u64, State> = Map::new("amalgams");
+/// Id of the last created Amalgam. Ids start at 1.
+pub const AMALGAM_SEQ: Item<u64> = Item::new("amalgam_seq");
+/// Amalgam whose denom is being created, awaiting the reply with the denom reported by tokenfactory.
+pub const PENDING_AMALGAM: Item<u64> = Item::new("pending_amalgam");
+/// Whether user actions are suspended by governance, for all Amalgams.
+pub const PAUSED: Item<bool> = Item::new("paused");
+/// Factory that deployed the contract, if any.
+pub const FACTORY: Item<Addr> = Item::new("factory");
+pub const COMPONENTS: Map<(u64, &AssetInfo), Component> = Map::new("components");
+/// Map of Amalgams and assets to balances, snapshotted for historical backing queries.
+pub const BALANCES: SnapshotMap<(u64, &AssetInfo), Uint128> = SnapshotMap::new(
+  "balances",
+  "balances__checkpoints",
+  "balances__changelog",
+  Strategy::EveryBlock,
+);
+/// Total supply of each Amalgam, snapshotted for historical supply queries.
+pub const SUPPLY: SnapshotMap<u64, Uint128> = SnapshotMap::new(
+  "supply",
+  "supply__checkpoints",
+  "supply__changelog",
+  Strategy::EveryBlock,
+);
+/// Map of Amalgams and assets to the Amalgam minted against them, net of withdrawals.
+pub const MINTED: Map<(u64, &AssetInfo), Uint128> = Map::new("minted");
+/// Map of Amalgams and assets to the withdrawal taxes accrued and not yet collected.
+pub const TAXES: Map<(u64, &AssetInfo), Uint128> = Map::new("taxes");
+/// Map of Amalgams, donors and assets to the total amount donated.
+pub const DONATIONS: Map<(u64, &Addr, &AssetInfo), Uint128> = Map::new("donations");
+
+pub fn positions<'a>() -> IndexedMap<'a, (u64, &'a Addr, &'a AssetInfo), Position, PositionIndexes<'a>> {
+  let indexes = PositionIndexes {
+    asset: MultiIndex::new(|_, position| (position.amalgam, position.asset.clone()), "positions", "positions__asset"),
+  };
+  IndexedMap::new("positions", indexes)
+}
+
+/// Append-only log of deposits and withdrawals by Amalgam, user and entry id, if enabled with `State::track_history`.
+pub const HISTORY: Map<(u64, &Addr, u64), HistoryEntry> = Map::new("history");
+/// Id of the next `HISTORY` entry, shared by all Amalgams.
+pub const HISTORY_SEQ: Item<u64> = Item::new("history_seq");
+
+pub const STAKING: Map<u64, StakingConfig> = Map::new("staking");
+/// Map of Amalgams and stakers to their staked Amalgam, snapshotted for voting power queries.
+pub const STAKED_BALANCES: SnapshotMap<(u64, &Addr), Uint128> = SnapshotMap::new(
+  "staked_balances",
+  "staked_balances__checkpoints",
+  "staked_balances__changelog",
+  Strategy::EveryBlock,
+);
+pub const STAKED_TOTAL: SnapshotMap<u64, Uint128> = SnapshotMap::new(
+  "staked_total",
+  "staked_total__checkpoints",
+  "staked_total__changelog",
+  Strategy::EveryBlock,
+);
+/// Maximum number of pending claims per address, to bound the gas cost of claiming.
+pub const MAX_CLAIMS: usize = 70;
+/// Map of Amalgams and stakers to their unstaked Amalgam pending release.
+pub const CLAIMS: Map<(u64, &Addr), Vec<Claim>> = Map::new("claims");
+
+/// Vote-escrow configuration of each Amalgam. Locking is disabled if unset.
+pub const VOTE_ESCROW: Map<u64, VoteEscrowConfig> = Map::new("vote_escrow");
+/// Map of Amalgams and owners to their lock, snapshotted for voting power queries.
+pub const LOCKS: SnapshotMap<(u64, &Addr), VeLock> = SnapshotMap::new(
+  "locks",
+  "locks__checkpoints",
+  "locks__changelog",
+  Strategy::EveryBlock,
+);
+/// Map of Amalgams and assets to the amount held by locks. Locks are valued along the conversion curve after the
+/// tracked balance and all earlier locks, so splitting a lock gains nothing.
+pub const LOCKED: Map<(u64, &AssetInfo), Uint128> = Map::new("locked");
+/// Checkpoints of the total vote-escrow power curve by Amalgam and height.
+pub const VE_POINTS: Map<(u64, u64), VePoint> = Map::new("ve_points");
+/// Decrease of the slope of the total vote-escrow power curve by Amalgam and the height locks expire.
+pub const VE_SLOPE_CHANGES: Map<(u64, u64), Decimal256> = Map::new("ve_slope_changes");
+
+/// IBC withdrawal awaiting the reply with its packet sequence.
+pub const PENDING_TRANSFER: Item<IbcTransfer> = Item::new("pending_transfer");
+/// IBC withdrawals in flight by source channel and packet sequence.
+pub const IBC_TRANSFERS: Map<(&str, u64), IbcTransfer> = Map::new("ibc_transfers");
+/// Map of Amalgams, withdrawers and assets to their claimable refunds.
+pub const REFUNDS: Map<(u64, &Addr, &AssetInfo), Refund> = Map::new("refunds");
@@ -0,0 +1,147 @@
+#[cfg(not(feature = "library"))]
+use cosmwasm_std::entry_point;
+use cosmwasm_std::{DepsMut, Env, Response};
+
+use amalgam_utils::ibc_hooks::IbcLifecycleComplete;
+
+use crate::{ContractError, ContractResult};
+use crate::exec::{send_taxes, set_admin};
+use crate::msg::SudoMsg;
+use crate::state::{Refund, IBC_TRANSFERS, PAUSED, REFUNDS};
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn sudo(deps: DepsMut, env: Env, msg: SudoMsg) -> ContractResult<Response> {
+  match msg {
+    SudoMsg::IbcLifecycleComplete(IbcLifecycleComplete::IbcAck { channel, sequence, success, .. }) =>
+      ibc_lifecycle_complete(deps, env, channel, sequence, success),
+    SudoMsg::IbcLifecycleComplete(IbcLifecycleComplete::IbcTimeout { channel, sequence }) =>
+      ibc_lifecycle_complete(deps, env, channel, sequence, false),
+    SudoMsg::UpdateAdmin { amalgam, admin } => {
+      let admin = deps.api.addr_validate(&admin)?;
+      set_admin(deps, amalgam, admin.to_string())
+    }
+    SudoMsg::Pause {} => {
+      PAUSED.save(deps.storage, &true)?;
+      Ok(Response::new().add_attribute("action", "pause"))
+    }
+    SudoMsg::Unpause {} => {
+      PAUSED.remove(deps.storage);
+      Ok(Response::new().add_attribute("action", "unpause"))
+    }
+    SudoMsg::CollectTaxes { amalgam, asset, recipient } => {
+      let asset = asset.check(deps.api)?;
+      let recipient = deps.api.addr_validate(&recipient)?;
+      send_taxes(deps, amalgam, asset, recipient.to_string())
+    }
+  }
+}
+
+/// Settle an IBC withdrawal. Failed and timed out transfers return the component to the contract, where it is
+/// credited as a refund claimable by the withdrawer.
+fn ibc_lifecycle_complete(deps: DepsMut, _env: Env, channel: String, sequence: u64, success: bool) -> ContractResult<Response> {
+  let transfer = IBC_TRANSFERS.may_load(deps.storage, (&channel, sequence))?
+    .ok_or(ContractError::UnknownTransfer)?;
+  IBC_TRANSFERS.remove(deps.storage, (&channel, sequence));
+
+  let res = Response::new()
+    .add_attribute("action", "ibc_lifecycle_complete")
+    .add_attribute("sender", transfer.sender.to_string())
+    .add_attribute("success", success.to_string());
+
+  if success {
+    return Ok(res);
+  }
+
+  let key = (transfer.amalgam, &transfer.sender, &transfer.asset);
+  REFUNDS.update(deps.storage, key, |refund| -> ContractResult<_> {
+    let mut refund = refund.unwrap_or(Refund { asset: transfer.asset.clone(), amount: Default::default() });
+    refund.amount += transfer.amount;
+    Ok(refund)
+  })?;
+
+  Ok(res.add_attribute("refund", transfer.amount))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use amalgam_utils::asset::{AssetInfo, AssetInfoUnchecked};
+  use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+  use cosmwasm_std::{coin, coins, BankMsg, CosmosMsg, Uint128};
+
+  use crate::exec::execute;
+  use crate::msg::ExecuteMsg;
+  use crate::state::{State, AMALGAMS, TAXES};
+
+  fn setup_state(deps: DepsMut) {
+    AMALGAMS.save(deps.storage, 1, &State {
+      admin: "admin".to_string(),
+      fee_recipient: None,
+      track_history: false,
+      denom: "factory/cosmos2contract/amalgam".to_string(),
+    }).unwrap();
+  }
+
+  #[test]
+  fn test_update_admin() {
+    let mut deps = mock_dependencies();
+    let env = mock_env();
+    setup_state(deps.as_mut());
+
+    let res = sudo(deps.as_mut(), env.clone(), SudoMsg::UpdateAdmin { amalgam: 1, admin: "gov_admin".to_string() }).unwrap();
+    let expected = execute(deps.as_mut(), env.clone(), mock_info("gov_admin", &[]), ExecuteMsg::UpdateAdmin {
+      amalgam: 1,
+      admin: "gov_admin".to_string(),
+    }).unwrap();
+    assert_eq!(res.attributes, expected.attributes);
+    assert_eq!(AMALGAMS.load(deps.as_ref().storage, 1).unwrap().admin, "gov_admin");
+
+    let res = sudo(deps.as_mut(), env, SudoMsg::UpdateAdmin { amalgam: 2, admin: "gov_admin".to_string() });
+    assert!(matches!(res, Err(ContractError::UnknownAmalgam)));
+  }
+
+  #[test]
+  fn test_pause() {
+    let mut deps = mock_dependencies();
+    let env = mock_env();
+    setup_state(deps.as_mut());
+
+    sudo(deps.as_mut(), env.clone(), SudoMsg::Pause {}).unwrap();
+
+    let info = mock_info("user", &[coin(1000, "uosmo")]);
+    let deposit = ExecuteMsg::Deposit { amalgam: 1, lock: None, recipient: None };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), deposit.clone());
+    assert!(matches!(res, Err(ContractError::Paused)));
+
+    // admins can still act
+    execute(deps.as_mut(), env.clone(), mock_info("admin", &[]), ExecuteMsg::UpdateFeeRecipient {
+      amalgam: 1,
+      fee_recipient: None,
+    }).unwrap();
+
+    sudo(deps.as_mut(), env.clone(), SudoMsg::Unpause {}).unwrap();
+    let res = execute(deps.as_mut(), env, info, deposit);
+    assert!(matches!(res, Err(ContractError::UnknownAsset)));
+  }
+
+  #[test]
+  fn test_collect_taxes() {
+    let mut deps = mock_dependencies();
+    let env = mock_env();
+    setup_state(deps.as_mut());
+
+    TAXES.save(deps.as_mut().storage, (1, &AssetInfo::Native("uosmo".to_string())), &Uint128::from(500u64)).unwrap();
+
+    let res = sudo(deps.as_mut(), env, SudoMsg::CollectTaxes {
+      amalgam: 1,
+      asset: AssetInfoUnchecked::Native("uosmo".to_string()),
+      recipient: "community_pool".to_string(),
+    }).unwrap();
+    assert_eq!(res.attributes[0].value, "collect_taxes");
+    assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+      to_address: "community_pool".to_string(),
+      amount: coins(500, "uosmo"),
+    }));
+  }
+}
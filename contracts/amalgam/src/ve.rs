@@ -0,0 +1,147 @@
+//! Vote-escrow accounting.
+//!
+//! Components locked through `Deposit { lock }` are held by the contract instead of backing newly minted Amalgam.
+//! Each lock grants voting power proportional to the Amalgam the locked components are worth, scaled by the remaining
+//! lock duration, so the power decays linearly to zero at the end of the lock:
+//!
+//! ```text
+//! power(h) = amalgam * max_multiplier * (end - h) / max_lock_duration
+//! ```
+//!
+//! Durations are measured in blocks so the power can be computed exactly at any height. The total power is tracked as
+//! a piecewise linear curve: checkpoints of its value (`bias`) and rate of decay (`slope`) whenever a lock changes,
+//! plus the changes in slope scheduled at the end of every lock. Each Amalgam has its own curve.
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{Decimal, Decimal256, Order, StdResult, Storage, Uint128};
+use cw_storage_plus::Bound;
+
+use amalgam_utils::asset::AssetInfo;
+
+use crate::state::{VE_POINTS, VE_SLOPE_CHANGES};
+
+#[cw_serde]
+pub struct VoteEscrowConfig {
+  /// Longest possible lock, in blocks.
+  pub max_lock_duration: u64,
+  /// Voting power per Amalgam of a lock of `max_lock_duration`.
+  pub max_multiplier: Decimal,
+}
+
+#[cw_serde]
+pub struct LockedAsset {
+  pub asset: AssetInfo,
+  pub amount: Uint128,
+}
+
+#[cw_serde]
+pub struct VeLock {
+  /// Components held by the lock, returned by `WithdrawExpired`.
+  pub assets: Vec<LockedAsset>,
+  /// Amalgam the components were worth when locked.
+  pub amalgam: Uint128,
+  /// Height at which the lock expires.
+  pub end: u64,
+}
+
+impl VeLock {
+  /// Voting power lost per block.
+  pub fn slope(&self, config: &VoteEscrowConfig) -> Decimal256 {
+    Decimal256::from_ratio(self.amalgam, config.max_lock_duration) * Decimal256::from(config.max_multiplier)
+  }
+
+  pub fn power_at(&self, config: &VoteEscrowConfig, height: u64) -> StdResult<Uint128> {
+    if height >= self.end {
+      return Ok(Uint128::zero());
+    }
+    floor(self.slope(config) * blocks(self.end - height))
+  }
+
+  /// Add `amount` of `asset` worth `amalgam` to the lock.
+  pub fn add(&mut self, asset: &AssetInfo, amount: Uint128, amalgam: Uint128) {
+    match self.assets.iter_mut().find(|locked| &locked.asset == asset) {
+      Some(locked) => locked.amount += amount,
+      None => self.assets.push(LockedAsset { asset: asset.clone(), amount }),
+    }
+    self.amalgam += amalgam;
+  }
+}
+
+/// Point of the total power curve.
+#[cw_serde]
+#[derive(Default)]
+pub struct VePoint {
+  pub bias: Decimal256,
+  pub slope: Decimal256,
+}
+
+/// Total voting power of all locks of `amalgam` at the start of `height`.
+pub fn total_power_at(storage: &dyn Storage, amalgam: u64, height: u64) -> StdResult<Uint128> {
+  floor(point_at(storage, amalgam, height, false)?.bias)
+}
+
+/// Update the total power curve of `amalgam` at `height` for a lock changing from `old` to `new`.
+pub fn checkpoint(
+  storage: &mut dyn Storage,
+  config: &VoteEscrowConfig,
+  amalgam: u64,
+  height: u64,
+  old: Option<&VeLock>,
+  new: Option<&VeLock>,
+) -> StdResult<()> {
+  let mut point = point_at(storage, amalgam, height, true)?;
+
+  // expired locks no longer contribute, and their slope change has already been applied
+  if let Some(old) = old.filter(|lock| lock.end > height) {
+    let slope = old.slope(config);
+    point.bias = point.bias.saturating_sub(slope * blocks(old.end - height));
+    point.slope = point.slope.saturating_sub(slope);
+    VE_SLOPE_CHANGES.update(storage, (amalgam, old.end), |change| -> StdResult<_> {
+      Ok(change.unwrap_or_default().saturating_sub(slope))
+    })?;
+  }
+
+  if let Some(new) = new.filter(|lock| lock.end > height) {
+    let slope = new.slope(config);
+    point.bias += slope * blocks(new.end - height);
+    point.slope += slope;
+    VE_SLOPE_CHANGES.update(storage, (amalgam, new.end), |change| -> StdResult<_> {
+      Ok(change.unwrap_or_default() + slope)
+    })?;
+  }
+
+  VE_POINTS.save(storage, (amalgam, height), &point)
+}
+
+/// Evaluate the total power curve at `height`, including the changes made during `height` if `inclusive`.
+fn point_at(storage: &dyn Storage, amalgam: u64, height: u64, inclusive: bool) -> StdResult<VePoint> {
+  let max = if inclusive { Bound::inclusive(height) } else { Bound::exclusive(height) };
+  let last = VE_POINTS
+    .prefix(amalgam)
+    .range(storage, None, Some(max), Order::Descending)
+    .next()
+    .transpose()?;
+  let Some((mut prev, mut point)) = last else {
+    return Ok(VePoint::default());
+  };
+
+  let changes = VE_SLOPE_CHANGES
+    .prefix(amalgam)
+    .range(storage, Some(Bound::exclusive(prev)), Some(Bound::inclusive(height)), Order::Ascending);
+  for change in changes {
+    let (at, change) = change?;
+    point.bias = point.bias.saturating_sub(point.slope * blocks(at - prev));
+    point.slope = point.slope.saturating_sub(change);
+    prev = at;
+  }
+  point.bias = point.bias.saturating_sub(point.slope * blocks(height - prev));
+
+  Ok(point)
+}
+
+fn blocks(count: u64) -> Decimal256 {
+  Decimal256::from_ratio(count, 1u64)
+}
+
+fn floor(value: Decimal256) -> StdResult<Uint128> {
+  Ok(Uint128::try_from(value.to_uint_floor())?)
+}
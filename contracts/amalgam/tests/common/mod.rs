@@ -0,0 +1,38 @@
+use amalgam::msg::InstantiateMsg;
+use amalgam::state::{State, AMALGAMS, AMALGAM_SEQ};
+use amalgam::ContractError;
+use cosmwasm_std::{Addr, DepsMut, Empty, Env, MessageInfo, Response};
+use cw_multi_test::{App, Contract, ContractWrapper, Executor};
+
+/// Id of the Amalgam created by `instantiate_amalgam`.
+pub const AMALGAM_ID: u64 = 1;
+
+/// Instantiate with a single Amalgam administered by the instantiator, without creating the tokenfactory denom, which
+/// multi-test cannot execute.
+fn instantiate(deps: DepsMut, env: Env, info: MessageInfo, _msg: InstantiateMsg) -> Result<Response, ContractError> {
+  AMALGAMS.save(deps.storage, AMALGAM_ID, &State {
+    admin: info.sender.to_string(),
+    fee_recipient: None,
+    track_history: false,
+    denom: format!("factory/{}/amalgam", env.contract.address),
+  })?;
+  AMALGAM_SEQ.save(deps.storage, &AMALGAM_ID)?;
+  Ok(Response::new())
+}
+
+pub fn amalgam_contract() -> Box<dyn Contract<Empty>> {
+  Box::new(ContractWrapper::new(amalgam::exec::execute, instantiate, amalgam::query::query))
+}
+
+/// Instantiate an Amalgam contract with Amalgam `AMALGAM_ID` administered by `admin`.
+pub fn instantiate_amalgam(app: &mut App, admin: &Addr) -> Addr {
+  let code_id = app.store_code(amalgam_contract());
+  app.instantiate_contract(
+    code_id,
+    admin.clone(),
+    &InstantiateMsg { factory: None },
+    &[],
+    "amalgam",
+    None,
+  ).unwrap()
+}
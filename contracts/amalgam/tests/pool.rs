@@ -0,0 +1,148 @@
+use amalgam::conversion::{ConversionFunction, ConversionSource, Ratio};
+use amalgam::msg::{ExecuteMsg, QueryMsg, SimulateDepositResponse};
+use amalgam::state::Component;
+use amalgam::ContractError;
+use amalgam_utils::asset::{self, AssetInfoUnchecked};
+use amalgam_utils::pool::astroport::{self, AssetInfo, PairInfo, PoolResponse};
+use cosmwasm_std::{to_json_binary, Addr, Binary, Decimal, Deps, DepsMut, Empty, Env, MessageInfo, Response, StdResult, Uint128};
+use cw_multi_test::{App, AppResponse, Contract, ContractWrapper, Executor};
+use cw_storage_plus::Item;
+
+mod common;
+use common::{instantiate_amalgam, AMALGAM_ID};
+
+mod mock_pair {
+  use super::*;
+
+  const POOL: Item<PoolResponse> = Item::new("pool");
+
+  pub fn instantiate(deps: DepsMut, _env: Env, _info: MessageInfo, msg: PoolResponse) -> StdResult<Response> {
+    POOL.save(deps.storage, &msg)?;
+    Ok(Response::new())
+  }
+
+  /// Replace the pool state, e.g. to simulate swaps and liquidity changes.
+  pub fn execute(deps: DepsMut, _env: Env, _info: MessageInfo, msg: PoolResponse) -> StdResult<Response> {
+    POOL.save(deps.storage, &msg)?;
+    Ok(Response::new())
+  }
+
+  pub fn query(deps: Deps, env: Env, msg: astroport::QueryMsg) -> StdResult<Binary> {
+    match msg {
+      astroport::QueryMsg::Pair {} => to_json_binary(&PairInfo { liquidity_token: lp_token(&env.contract.address) }),
+      astroport::QueryMsg::Pool {} => to_json_binary(&POOL.load(deps.storage)?),
+    }
+  }
+
+  pub fn contract() -> Box<dyn Contract<Empty>> {
+    Box::new(ContractWrapper::new(execute, instantiate, query))
+  }
+}
+
+fn lp_token(pair: &Addr) -> String {
+  format!("factory/{}/astroport/share", pair)
+}
+
+fn pool(uosmo: u128, uatom: u128, total_share: u128) -> PoolResponse {
+  PoolResponse {
+    assets: vec![
+      astroport::Asset {
+        info: AssetInfo::NativeToken { denom: "uosmo".to_string() },
+        amount: Uint128::new(uosmo),
+      },
+      astroport::Asset {
+        info: AssetInfo::NativeToken { denom: "uatom".to_string() },
+        amount: Uint128::new(uatom),
+      },
+    ],
+    total_share: Uint128::new(total_share),
+  }
+}
+
+fn add_component(app: &mut App, amalgam: &Addr, pair: &Addr, token: String, reference: &str) -> Result<AppResponse, ContractError> {
+  app.execute_contract(
+    Addr::unchecked("admin"),
+    amalgam.clone(),
+    &ExecuteMsg::AddComponent {
+      amalgam: AMALGAM_ID,
+      component: Component {
+        token: AssetInfoUnchecked::Native(token),
+        conversion: ConversionFunction::Source(ConversionSource::AstroportPool {
+          pair: pair.to_string(),
+          reference: asset::AssetInfo::Native(reference.to_string()),
+          weight: Ratio::one(),
+          min_rate: Decimal::percent(180),
+          max_rate: Decimal::percent(216),
+        }),
+        withdrawal_tax: 0,
+        deposit_fee: None,
+        decimals: None,
+      },
+      display_units: None,
+    },
+    &[],
+  ).map_err(|err| err.downcast().unwrap())
+}
+
+fn setup(reference: &str) -> (App, Addr, Addr) {
+  let mut app = App::default();
+  let admin = Addr::unchecked("admin");
+
+  let pair_code = app.store_code(mock_pair::contract());
+  let pair = app.instantiate_contract(pair_code, admin.clone(), &pool(4000, 1000, 2000), &[], "pair", None).unwrap();
+
+  let amalgam = instantiate_amalgam(&mut app, &admin);
+  add_component(&mut app, &amalgam, &pair, lp_token(&pair), reference).unwrap();
+
+  (app, amalgam, pair)
+}
+
+fn simulate_deposit(app: &App, amalgam: &Addr, pair: &Addr, amount: u128) -> StdResult<SimulateDepositResponse> {
+  app.wrap().query_wasm_smart(amalgam, &QueryMsg::SimulateDeposit {
+    amalgam: AMALGAM_ID,
+    asset: AssetInfoUnchecked::Native(lp_token(pair)),
+    amount: Uint128::new(amount),
+  })
+}
+
+#[test]
+fn test_pool_rate() {
+  let (mut app, amalgam, pair) = setup("uosmo");
+
+  // 4000 uosmo backing 2000 shares
+  let res = simulate_deposit(&app, &amalgam, &pair, 100).unwrap();
+  assert_eq!(res.amount, Uint128::new(200));
+
+  // liquidity was added & the pool grew through fees
+  app.execute_contract(Addr::unchecked("anyone"), pair.clone(), &pool(8400, 2000, 4000), &[]).unwrap();
+
+  let res = simulate_deposit(&app, &amalgam, &pair, 100).unwrap();
+  assert_eq!(res.amount, Uint128::new(210));
+}
+
+#[test]
+fn test_pool_rate_out_of_bounds() {
+  let (mut app, amalgam, pair) = setup("uosmo");
+
+  app.execute_contract(Addr::unchecked("anyone"), pair.clone(), &pool(100, 40000, 2000), &[]).unwrap();
+
+  let err = simulate_deposit(&app, &amalgam, &pair, 100).unwrap_err();
+  assert!(err.to_string().contains(&ContractError::RateOutOfBounds.to_string()));
+}
+
+#[test]
+fn test_pool_unknown_reference() {
+  let (app, amalgam, pair) = setup("uluna");
+
+  let err = simulate_deposit(&app, &amalgam, &pair, 100).unwrap_err();
+  assert!(err.to_string().contains(&ContractError::UnknownReferenceAsset.to_string()));
+}
+
+#[test]
+fn test_pool_token_mismatch() {
+  let (mut app, amalgam, pair) = setup("uosmo");
+
+  // the pair cannot value the shares of another pool
+  let res = add_component(&mut app, &amalgam, &pair, "gamm/pool/1".to_string(), "uosmo");
+  assert!(matches!(res, Err(ContractError::PoolTokenMismatch)));
+}
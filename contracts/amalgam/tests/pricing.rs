@@ -0,0 +1,186 @@
+use amalgam::conversion::{ConversionFunction, ConversionSource};
+use amalgam::msg::{ExecuteMsg, QueryMsg, SimulateDepositResponse};
+use amalgam::state::Component;
+use amalgam_utils::asset::AssetInfoUnchecked;
+use amalgam::ContractError;
+use amalgam_utils::pricing::{PricingQueryMsg, RateResponse};
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{coins, to_json_binary, Addr, Binary, Decimal, Deps, DepsMut, Empty, Env, MessageInfo, Response, StdResult, Uint128};
+use cw_multi_test::{App, Contract, ContractWrapper, Executor};
+use cw_storage_plus::Item;
+
+mod common;
+use common::{instantiate_amalgam, AMALGAM_ID};
+
+mod mock_pricing {
+  use super::*;
+
+  const RATE: Item<RateResponse> = Item::new("rate");
+
+  #[cw_serde]
+  pub enum ExecuteMsg {
+    SetRate(RateResponse),
+  }
+
+  pub fn instantiate(deps: DepsMut, _env: Env, _info: MessageInfo, msg: RateResponse) -> StdResult<Response> {
+    RATE.save(deps.storage, &msg)?;
+    Ok(Response::new())
+  }
+
+  pub fn execute(deps: DepsMut, _env: Env, _info: MessageInfo, msg: ExecuteMsg) -> StdResult<Response> {
+    match msg {
+      ExecuteMsg::SetRate(rate) => RATE.save(deps.storage, &rate)?,
+    }
+    Ok(Response::new())
+  }
+
+  pub fn query(deps: Deps, _env: Env, msg: PricingQueryMsg) -> StdResult<Binary> {
+    match msg {
+      PricingQueryMsg::Rate { token } => {
+        assert_eq!(token, "uosmo");
+        to_json_binary(&RATE.load(deps.storage)?)
+      }
+    }
+  }
+
+  pub fn contract() -> Box<dyn Contract<Empty>> {
+    Box::new(ContractWrapper::new(execute, instantiate, query))
+  }
+}
+
+fn setup(rate: Decimal) -> (App, Addr, Addr) {
+  let mut app = App::default();
+  let admin = Addr::unchecked("admin");
+
+  let pricing_code = app.store_code(mock_pricing::contract());
+  let updated_at = app.block_info().time;
+  let pricing = app.instantiate_contract(
+    pricing_code,
+    admin.clone(),
+    &RateResponse { rate, updated_at },
+    &[],
+    "pricing",
+    None,
+  ).unwrap();
+
+  let amalgam = instantiate_amalgam(&mut app, &admin);
+
+  app.execute_contract(
+    admin,
+    amalgam.clone(),
+    &ExecuteMsg::AddComponent {
+      amalgam: AMALGAM_ID,
+      component: Component {
+        token: AssetInfoUnchecked::Native("uosmo".to_string()),
+        conversion: ConversionFunction::Source(ConversionSource::Contract {
+          addr: pricing.to_string(),
+          max_staleness: 3600,
+          min_rate: Decimal::percent(50),
+          max_rate: Decimal::percent(200),
+        }),
+        withdrawal_tax: 0,
+        deposit_fee: None,
+        decimals: None,
+      },
+      display_units: None,
+    },
+    &[],
+  ).unwrap();
+
+  (app, amalgam, pricing)
+}
+
+fn simulate_deposit(app: &App, amalgam: &Addr, amount: u128) -> StdResult<SimulateDepositResponse> {
+  app.wrap().query_wasm_smart(amalgam, &QueryMsg::SimulateDeposit {
+    amalgam: AMALGAM_ID,
+    asset: AssetInfoUnchecked::Native("uosmo".to_string()),
+    amount: Uint128::new(amount),
+  })
+}
+
+#[test]
+fn test_contract_rate() {
+  let (mut app, amalgam, pricing) = setup(Decimal::percent(150));
+
+  let res = simulate_deposit(&app, &amalgam, 1000).unwrap();
+  assert_eq!(res.amount, Uint128::new(1500));
+
+  let updated_at = app.block_info().time;
+  app.execute_contract(
+    Addr::unchecked("oracle"),
+    pricing,
+    &mock_pricing::ExecuteMsg::SetRate(RateResponse { rate: Decimal::percent(80), updated_at }),
+    &[],
+  ).unwrap();
+
+  let res = simulate_deposit(&app, &amalgam, 1000).unwrap();
+  assert_eq!(res.amount, Uint128::new(800));
+}
+
+#[test]
+fn test_contract_rate_out_of_bounds() {
+  let (mut app, amalgam, pricing) = setup(Decimal::percent(150));
+
+  let updated_at = app.block_info().time;
+  app.execute_contract(
+    Addr::unchecked("oracle"),
+    pricing,
+    &mock_pricing::ExecuteMsg::SetRate(RateResponse { rate: Decimal::percent(300), updated_at }),
+    &[],
+  ).unwrap();
+
+  let err = simulate_deposit(&app, &amalgam, 1000).unwrap_err();
+  assert!(err.to_string().contains(&ContractError::RateOutOfBounds.to_string()));
+}
+
+#[test]
+fn test_contract_rate_stale() {
+  let (mut app, amalgam, _) = setup(Decimal::percent(150));
+  let user = Addr::unchecked("user");
+
+  app.init_modules(|router, _, storage| {
+    router.bank.init_balance(storage, &user, coins(1000, "uosmo"))
+  }).unwrap();
+
+  app.update_block(|block| block.time = block.time.plus_seconds(3601));
+
+  let err = simulate_deposit(&app, &amalgam, 1000).unwrap_err();
+  assert!(err.to_string().contains(&ContractError::StaleRate.to_string()));
+
+  let err = app.execute_contract(
+    user,
+    amalgam,
+    &ExecuteMsg::Deposit { amalgam: AMALGAM_ID, lock: None, recipient: None },
+    &coins(1000, "uosmo"),
+  ).unwrap_err();
+  assert!(matches!(err.downcast::<ContractError>(), Ok(ContractError::StaleRate)));
+}
+
+#[test]
+fn test_contract_source_validated() {
+  let mut app = App::default();
+  let amalgam = instantiate_amalgam(&mut app, &Addr::unchecked("admin"));
+
+  let err = app.execute_contract(
+    Addr::unchecked("admin"),
+    amalgam,
+    &ExecuteMsg::AddComponent {
+      amalgam: AMALGAM_ID,
+      component: Component {
+        token: AssetInfoUnchecked::Native("uosmo".to_string()),
+        conversion: ConversionFunction::Source(ConversionSource::Contract {
+          addr: "pricing".to_string(),
+          max_staleness: 3600,
+          min_rate: Decimal::percent(200),
+          max_rate: Decimal::percent(50),
+        }),
+        withdrawal_tax: 0,
+        deposit_fee: None,
+        decimals: None,
+      },
+      display_units: None,
+    },
+    &[],
+  ).unwrap_err();
+  assert!(matches!(err.downcast::<ContractError>(), Ok(ContractError::InvalidConversionFunction)));
+}
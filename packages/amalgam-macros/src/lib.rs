@@ -1,33 +1,203 @@
 use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
 use quote::quote;
-use syn;
+use syn::spanned::Spanned;
+use syn::{parse_quote, DeriveInput, Generics, Ident, LitStr};
 
+/// Attach a protobuf type url to a `prost::Message` struct, e.g. `#[typeurl("/cosmos.bank.v1beta1.Coin")]`.
+///
+/// Generates the `TYPE_URL` constant, the conversion into a `CosmosMsg::Stargate`, decoding from `Binary` and
+/// `to_any()`. Generated code refers to `amalgam_utils`, `cosmwasm_std` and `prost`, which must be available to the
+/// annotated crate.
 #[proc_macro_attribute]
 pub fn typeurl(args: TokenStream, input: TokenStream) -> TokenStream {
-  let input = syn::parse_macro_input!(input as syn::Item);
-  let args = syn::parse_macro_input!(args as syn::LitStr);
-  let type_url = args.value();
+  let item = syn::parse_macro_input!(input as syn::Item);
+  let expanded = match &item {
+    syn::Item::Struct(item_struct) => parse_type_url(args.into(), Span::call_site())
+      .map(|type_url| expand(&item_struct.ident, &item_struct.generics, &type_url)),
+    _ => Err(syn::Error::new(item_span(&item), "typeurl can only be applied to structs")),
+  };
+  let expanded = expanded.unwrap_or_else(syn::Error::into_compile_error);
+
+  quote! {
+    #item
+    #expanded
+  }
+  .into()
+}
+
+/// Derive the same implementations as [`macro@typeurl`], with the type url given by `#[type_url("...")]`.
+#[proc_macro_derive(TypeUrl, attributes(type_url))]
+pub fn derive_type_url(input: TokenStream) -> TokenStream {
+  let input = syn::parse_macro_input!(input as DeriveInput);
+  derive(&input)
+    .unwrap_or_else(syn::Error::into_compile_error)
+    .into()
+}
 
-  let struct_name = match &input {
-    syn::Item::Struct(struct_item) => &struct_item.ident,
-    _ => panic!("typeurl macro can only be applied to structs"),
+/// Turn a protobuf request struct into a typed Stargate query, e.g.
+/// `#[stargate_query(path = "/osmosis.tokenfactory.v1beta1.Query/Params", response = QueryParamsResponse)]`.
+///
+/// Implements `amalgam_utils::stargate::StargateQuery` and generates a `query` method, which sends the request through a
+/// `QuerierWrapper` and decodes the protobuf response.
+#[proc_macro_attribute]
+pub fn stargate_query(args: TokenStream, input: TokenStream) -> TokenStream {
+  let item = syn::parse_macro_input!(input as syn::Item);
+  let expanded = match &item {
+    syn::Item::Struct(item_struct) => parse_query_args(args.into())
+      .map(|(path, response)| expand_query(&item_struct.ident, &item_struct.generics, &path, &response)),
+    _ => Err(syn::Error::new(item_span(&item), "stargate_query can only be applied to structs")),
   };
+  let expanded = expanded.unwrap_or_else(syn::Error::into_compile_error);
+
+  quote! {
+    #item
+    #expanded
+  }
+  .into()
+}
+
+fn derive(input: &DeriveInput) -> syn::Result<TokenStream2> {
+  if !matches!(input.data, syn::Data::Struct(_)) {
+    return Err(syn::Error::new(input.ident.span(), "TypeUrl can only be derived for structs"));
+  }
+
+  let mut attrs = input.attrs.iter().filter(|attr| attr.path().is_ident("type_url"));
+  let attr = attrs.next()
+    .ok_or_else(|| syn::Error::new(input.ident.span(), "missing #[type_url(\"...\")] attribute"))?;
+  if let Some(duplicate) = attrs.next() {
+    return Err(syn::Error::new_spanned(duplicate, "duplicate #[type_url] attribute"));
+  }
+  let type_url = match &attr.meta {
+    syn::Meta::List(list) => parse_type_url(list.tokens.clone(), list.span())?,
+    meta => return Err(syn::Error::new_spanned(meta, "expected #[type_url(\"...\")]")),
+  };
+
+  Ok(expand(&input.ident, &input.generics, &type_url))
+}
+
+/// Parse the type url literal, which must be a fully qualified protobuf name starting with a slash.
+fn parse_type_url(tokens: TokenStream2, span: Span) -> syn::Result<LitStr> {
+  if tokens.is_empty() {
+    return Err(syn::Error::new(span, "expected a type url, e.g. \"/cosmos.bank.v1beta1.Coin\""));
+  }
+  let type_url: LitStr = syn::parse2(tokens)?;
+  if !type_url.value().starts_with('/') || type_url.value().len() < 2 {
+    return Err(syn::Error::new(type_url.span(), "type url must start with '/', e.g. \"/cosmos.bank.v1beta1.Coin\""));
+  }
+  Ok(type_url)
+}
+
+/// Parse the `path = "...", response = Type` arguments of `stargate_query`.
+fn parse_query_args(tokens: TokenStream2) -> syn::Result<(LitStr, syn::Type)> {
+  let mut path: Option<LitStr> = None;
+  let mut response: Option<syn::Type> = None;
+  let parser = syn::meta::parser(|meta| {
+    if meta.path.is_ident("path") {
+      if path.is_some() {
+        return Err(meta.error("duplicate `path` argument"));
+      }
+      let value: LitStr = meta.value()?.parse()?;
+      if !value.value().starts_with('/') {
+        return Err(syn::Error::new(value.span(), "query path must start with '/', e.g. \"/cosmos.bank.v1beta1.Query/DenomMetadata\""));
+      }
+      path = Some(value);
+      Ok(())
+    } else if meta.path.is_ident("response") {
+      if response.is_some() {
+        return Err(meta.error("duplicate `response` argument"));
+      }
+      response = Some(meta.value()?.parse()?);
+      Ok(())
+    } else {
+      Err(meta.error("unsupported argument, expected `path` or `response`"))
+    }
+  });
+  syn::parse::Parser::parse2(parser, tokens)?;
+
+  match (path, response) {
+    (Some(path), Some(response)) => Ok((path, response)),
+    (None, _) => Err(syn::Error::new(Span::call_site(), "missing `path = \"...\"` argument")),
+    (_, None) => Err(syn::Error::new(Span::call_site(), "missing `response = Type` argument")),
+  }
+}
+
+fn expand_query(ident: &Ident, generics: &Generics, path: &LitStr, response: &syn::Type) -> TokenStream2 {
+  let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
 
   quote! {
-    #input
+    impl #impl_generics ::amalgam_utils::stargate::StargateQuery for #ident #ty_generics #where_clause {
+      const PATH: &'static str = #path;
+      type Response = #response;
+    }
 
-    impl #struct_name {
+    impl #impl_generics #ident #ty_generics #where_clause {
+      /// Send the query and decode its response.
+      pub fn query<C: ::cosmwasm_std::CustomQuery>(
+        self,
+        querier: &::cosmwasm_std::QuerierWrapper<C>,
+      ) -> ::cosmwasm_std::StdResult<#response> {
+        ::amalgam_utils::stargate::query(querier, self)
+      }
+    }
+  }
+}
+
+fn expand(ident: &Ident, generics: &Generics, type_url: &LitStr) -> TokenStream2 {
+  let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+  let mut message_generics = generics.clone();
+  message_generics.make_where_clause().predicates
+    .push(parse_quote! { #ident #ty_generics: ::prost::Message + ::core::default::Default });
+  let message_bounds = message_generics.where_clause;
+
+  quote! {
+    impl #impl_generics #ident #ty_generics #where_clause {
       pub const TYPE_URL: &'static str = #type_url;
     }
 
-    impl Into<cosmwasm_std::CosmosMsg> for #struct_name {
-      fn into(self) -> cosmwasm_std::CosmosMsg {
-        cosmwasm_std::CosmosMsg::Stargate {
+    impl #impl_generics #ident #ty_generics #message_bounds {
+      /// Wrap the encoded message into an `Any` with its type url.
+      pub fn to_any(&self) -> ::amalgam_utils::stargate::Any {
+        ::amalgam_utils::stargate::Any {
           type_url: Self::TYPE_URL.to_string(),
-          value: self.encode_to_vec().into(),
+          value: ::prost::Message::encode_to_vec(self),
         }
       }
     }
+
+    impl #impl_generics ::core::convert::From<#ident #ty_generics> for ::cosmwasm_std::CosmosMsg #message_bounds {
+      fn from(msg: #ident #ty_generics) -> Self {
+        ::cosmwasm_std::CosmosMsg::Stargate {
+          type_url: <#ident #ty_generics>::TYPE_URL.to_string(),
+          value: ::prost::Message::encode_to_vec(&msg).into(),
+        }
+      }
+    }
+
+    impl #impl_generics ::core::convert::TryFrom<&::cosmwasm_std::Binary> for #ident #ty_generics #message_bounds {
+      type Error = ::cosmwasm_std::StdError;
+
+      fn try_from(value: &::cosmwasm_std::Binary) -> ::cosmwasm_std::StdResult<Self> {
+        <Self as ::prost::Message>::decode(value.as_slice())
+          .map_err(|err| ::cosmwasm_std::StdError::parse_err(#type_url, err))
+      }
+    }
+
+    impl #impl_generics ::core::convert::TryFrom<::cosmwasm_std::Binary> for #ident #ty_generics #message_bounds {
+      type Error = ::cosmwasm_std::StdError;
+
+      fn try_from(value: ::cosmwasm_std::Binary) -> ::cosmwasm_std::StdResult<Self> {
+        Self::try_from(&value)
+      }
+    }
+  }
+}
+
+fn item_span(item: &syn::Item) -> Span {
+  match item {
+    syn::Item::Enum(item) => item.enum_token.span(),
+    syn::Item::Union(item) => item.union_token.span(),
+    syn::Item::Fn(item) => item.sig.fn_token.span(),
+    item => item.span(),
   }
-  .into()
 }
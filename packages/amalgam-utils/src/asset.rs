@@ -0,0 +1,482 @@
+use std::fmt;
+
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{
+  to_json_binary, Addr, Api, BankMsg, Coin, CosmosMsg, QuerierWrapper, StdError, StdResult, Uint128, WasmMsg,
+};
+use cw20::{BalanceResponse, Cw20ExecuteMsg, Cw20QueryMsg, TokenInfoResponse};
+use cw_storage_plus::{Key, KeyDeserialize, Prefixer, PrimaryKey};
+
+use crate::tokenfactory::query_denom_metadata;
+
+/// Native denom or cw20 token, with the address of the cw20 contract of type `T`.
+#[cw_serde]
+#[derive(Eq, Hash)]
+pub enum AssetInfoBase<T> {
+  Native(String),
+  Cw20(T),
+}
+
+/// Asset info as received in messages, with an unvalidated cw20 address.
+pub type AssetInfoUnchecked = AssetInfoBase<String>;
+
+/// Asset info with a validated cw20 address.
+pub type AssetInfo = AssetInfoBase<Addr>;
+
+impl<T: fmt::Display> fmt::Display for AssetInfoBase<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      AssetInfoBase::Native(denom) => write!(f, "native:{}", denom),
+      AssetInfoBase::Cw20(contract) => write!(f, "cw20:{}", contract),
+    }
+  }
+}
+
+impl AssetInfoUnchecked {
+  /// Validate the format of the denom, or the address of the cw20 contract.
+  pub fn check(&self, api: &dyn Api) -> StdResult<AssetInfo> {
+    match self {
+      AssetInfoBase::Native(denom) => {
+        validate_denom(denom)?;
+        Ok(AssetInfo::Native(denom.clone()))
+      }
+      AssetInfoBase::Cw20(contract) => Ok(AssetInfo::Cw20(api.addr_validate(contract)?)),
+    }
+  }
+}
+
+impl From<AssetInfo> for AssetInfoUnchecked {
+  fn from(info: AssetInfo) -> Self {
+    match info {
+      AssetInfoBase::Native(denom) => AssetInfoBase::Native(denom),
+      AssetInfoBase::Cw20(contract) => AssetInfoBase::Cw20(contract.into_string()),
+    }
+  }
+}
+
+impl AssetInfo {
+  pub fn kind(&self) -> AssetKind {
+    match self {
+      AssetInfoBase::Native(_) => AssetKind::Native,
+      AssetInfoBase::Cw20(_) => AssetKind::Cw20,
+    }
+  }
+
+  /// Denom or cw20 contract address of the asset.
+  pub fn id(&self) -> &str {
+    match self {
+      AssetInfoBase::Native(denom) => denom,
+      AssetInfoBase::Cw20(contract) => contract.as_str(),
+    }
+  }
+
+  /// Check that a cw20 contract actually is a cw20 token by querying its `TokenInfo`. Native denoms always pass, as the
+  /// bank module cannot tell unknown denoms apart from denoms without supply.
+  pub fn assert_exists(&self, querier: &QuerierWrapper) -> StdResult<()> {
+    if let AssetInfoBase::Cw20(contract) = self {
+      querier.query_wasm_smart::<TokenInfoResponse>(contract, &Cw20QueryMsg::TokenInfo {})
+        .map_err(|err| StdError::generic_err(format!("{} is not a cw20 token: {}", contract, err)))?;
+    }
+    Ok(())
+  }
+
+  /// Number of decimals of the asset's display unit: the exponent of the display unit in the bank metadata of a native
+  /// denom, or the `decimals` of a cw20 token.
+  pub fn query_decimals(&self, querier: &QuerierWrapper) -> StdResult<u32> {
+    match self {
+      AssetInfoBase::Native(denom) => query_denom_metadata(querier, denom.clone())?.display_exponent(),
+      AssetInfoBase::Cw20(contract) => {
+        let res: TokenInfoResponse = querier.query_wasm_smart(contract, &Cw20QueryMsg::TokenInfo {})?;
+        Ok(res.decimals.into())
+      }
+    }
+  }
+
+  /// Balance of the asset held by `address`.
+  pub fn query_balance(&self, querier: &QuerierWrapper, address: impl Into<String>) -> StdResult<Uint128> {
+    match self {
+      AssetInfoBase::Native(denom) => Ok(querier.query_balance(address, denom)?.amount),
+      AssetInfoBase::Cw20(contract) => {
+        let res: BalanceResponse = querier.query_wasm_smart(contract, &Cw20QueryMsg::Balance {
+          address: address.into(),
+        })?;
+        Ok(res.balance)
+      }
+    }
+  }
+}
+
+/// Kind of an asset. Storage keys of assets start with their kind, so maps keyed by assets can be ranged over a
+/// single kind with `prefix`.
+#[cw_serde]
+#[derive(Copy, Eq, Hash)]
+pub enum AssetKind {
+  Native,
+  Cw20,
+}
+
+impl AssetKind {
+  fn as_bytes(&self) -> &'static [u8] {
+    match self {
+      AssetKind::Native => b"native",
+      AssetKind::Cw20 => b"cw20",
+    }
+  }
+}
+
+impl<'a> Prefixer<'a> for AssetKind {
+  fn prefix(&self) -> Vec<Key<'_>> {
+    vec![Key::Ref(self.as_bytes())]
+  }
+}
+
+impl KeyDeserialize for AssetKind {
+  type Output = AssetKind;
+
+  fn from_vec(value: Vec<u8>) -> StdResult<Self::Output> {
+    match value.as_slice() {
+      b"native" => Ok(AssetKind::Native),
+      b"cw20" => Ok(AssetKind::Cw20),
+      _ => Err(StdError::parse_err("AssetKind", "unknown asset kind")),
+    }
+  }
+}
+
+// Assets are stored under their kind followed by their denom or cw20 address, the same layout as a
+// `(AssetKind, &str)` key. Maps are keyed by `&AssetInfo` through the implementations for references.
+impl<'a> PrimaryKey<'a> for AssetInfo {
+  type Prefix = AssetKind;
+  type SubPrefix = ();
+  type Suffix = String;
+  type SuperSuffix = Self;
+
+  fn key(&self) -> Vec<Key<'_>> {
+    vec![Key::Ref(self.kind().as_bytes()), Key::Ref(self.id().as_bytes())]
+  }
+}
+
+impl<'a> Prefixer<'a> for AssetInfo {
+  fn prefix(&self) -> Vec<Key<'_>> {
+    self.key()
+  }
+}
+
+impl KeyDeserialize for &AssetInfo {
+  type Output = AssetInfo;
+
+  fn from_vec(value: Vec<u8>) -> StdResult<Self::Output> {
+    AssetInfo::from_vec(value)
+  }
+}
+
+impl KeyDeserialize for AssetInfo {
+  type Output = AssetInfo;
+
+  fn from_vec(value: Vec<u8>) -> StdResult<Self::Output> {
+    // addresses were validated before they were stored
+    match <(AssetKind, String)>::from_vec(value)? {
+      (AssetKind::Native, denom) => Ok(AssetInfo::Native(denom)),
+      (AssetKind::Cw20, contract) => Ok(AssetInfo::Cw20(Addr::unchecked(contract))),
+    }
+  }
+}
+
+/// Validate `denom` against the format of the Cosmos SDK: 3 to 128 characters, starting with a letter, followed by
+/// letters, digits or any of `/:._-`.
+pub fn validate_denom(denom: &str) -> StdResult<()> {
+  let mut chars = denom.chars();
+  let valid = (3..=128).contains(&denom.len())
+    && chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+    && chars.all(|c| c.is_ascii_alphanumeric() || "/:._-".contains(c));
+  if !valid {
+    return Err(StdError::generic_err(format!("Invalid denom: {}", denom)));
+  }
+  Ok(())
+}
+
+/// Amount of an asset.
+#[cw_serde]
+pub struct AssetBase<T> {
+  pub info: AssetInfoBase<T>,
+  pub amount: Uint128,
+}
+
+/// Amount of an asset as received in messages, with an unvalidated cw20 address.
+pub type AssetUnchecked = AssetBase<String>;
+
+/// Amount of an asset with a validated cw20 address.
+pub type Asset = AssetBase<Addr>;
+
+impl AssetUnchecked {
+  pub fn check(&self, api: &dyn Api) -> StdResult<Asset> {
+    Ok(Asset { info: self.info.check(api)?, amount: self.amount })
+  }
+}
+
+impl Asset {
+  pub fn new(info: AssetInfo, amount: impl Into<Uint128>) -> Self {
+    Self { info, amount: amount.into() }
+  }
+
+  pub fn native(denom: impl Into<String>, amount: impl Into<Uint128>) -> Self {
+    Self::new(AssetInfo::Native(denom.into()), amount)
+  }
+
+  pub fn cw20(contract: Addr, amount: impl Into<Uint128>) -> Self {
+    Self::new(AssetInfo::Cw20(contract), amount)
+  }
+
+  /// Message transferring the asset to `recipient`.
+  pub fn transfer_msg(&self, recipient: impl Into<String>) -> StdResult<CosmosMsg> {
+    match &self.info {
+      AssetInfoBase::Native(denom) => Ok(BankMsg::Send {
+        to_address: recipient.into(),
+        amount: vec![Coin { denom: denom.clone(), amount: self.amount }],
+      }.into()),
+      AssetInfoBase::Cw20(contract) => Ok(WasmMsg::Execute {
+        contract_addr: contract.to_string(),
+        msg: to_json_binary(&Cw20ExecuteMsg::Transfer { recipient: recipient.into(), amount: self.amount })?,
+        funds: vec![],
+      }.into()),
+    }
+  }
+}
+
+impl From<Coin> for Asset {
+  fn from(coin: Coin) -> Self {
+    Self::native(coin.denom, coin.amount)
+  }
+}
+
+impl fmt::Display for Asset {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}:{}", self.info, self.amount)
+  }
+}
+
+/// Amounts of distinct assets.
+#[cw_serde]
+#[derive(Default)]
+pub struct AssetList(Vec<Asset>);
+
+impl AssetList {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn len(&self) -> usize {
+    self.0.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = &Asset> {
+    self.0.iter()
+  }
+
+  /// Amount of `info` in the list, zero if absent.
+  pub fn amount(&self, info: &AssetInfo) -> Uint128 {
+    self.0.iter().find(|asset| &asset.info == info).map(|asset| asset.amount).unwrap_or_default()
+  }
+
+  /// Add `asset`, merging it into the entry of the same asset if any.
+  pub fn add(&mut self, asset: &Asset) -> StdResult<&mut Self> {
+    match self.0.iter_mut().find(|existing| existing.info == asset.info) {
+      Some(existing) => existing.amount = existing.amount.checked_add(asset.amount)?,
+      None => self.0.push(asset.clone()),
+    }
+    Ok(self)
+  }
+
+  /// Deduct `asset`, removing its entry once empty. Fails if the list holds less than `asset.amount`.
+  pub fn deduct(&mut self, asset: &Asset) -> StdResult<&mut Self> {
+    let Some(index) = self.0.iter().position(|existing| existing.info == asset.info) else {
+      return Err(StdError::generic_err(format!("Cannot deduct {}, asset not in list", asset)));
+    };
+    self.0[index].amount = self.0[index].amount.checked_sub(asset.amount)?;
+    if self.0[index].amount.is_zero() {
+      self.0.remove(index);
+    }
+    Ok(self)
+  }
+
+  /// Messages transferring all non-zero assets of the list to `recipient`.
+  pub fn transfer_msgs(&self, recipient: impl Into<String>) -> StdResult<Vec<CosmosMsg>> {
+    let recipient = recipient.into();
+    self.0.iter()
+      .filter(|asset| !asset.amount.is_zero())
+      .map(|asset| asset.transfer_msg(recipient.clone()))
+      .collect()
+  }
+}
+
+impl TryFrom<Vec<Asset>> for AssetList {
+  type Error = StdError;
+
+  /// Merge `assets` into a list, failing if the amounts of an asset overflow.
+  fn try_from(assets: Vec<Asset>) -> StdResult<Self> {
+    let mut list = Self::new();
+    for asset in &assets {
+      list.add(asset)?;
+    }
+    Ok(list)
+  }
+}
+
+impl IntoIterator for AssetList {
+  type Item = Asset;
+  type IntoIter = std::vec::IntoIter<Asset>;
+
+  fn into_iter(self) -> Self::IntoIter {
+    self.0.into_iter()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use cosmwasm_std::testing::{mock_dependencies, mock_dependencies_with_balance, MockApi};
+  use cosmwasm_std::{coins, from_json, ContractResult, Empty, Order, SystemError, SystemResult, WasmQuery};
+  use cw_storage_plus::Map;
+
+  #[test]
+  fn test_validate_denom() {
+    for denom in ["uosmo", "factory/osmo1abc/lp", "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2", "gamm/pool/1", "a.b:c_d-e"] {
+      assert!(validate_denom(denom).is_ok(), "{}", denom);
+    }
+    for denom in ["", "ab", "1uosmo", "/uosmo", "u osmo", "uosmo!", &"u".repeat(129)] {
+      assert!(validate_denom(denom).is_err(), "{}", denom);
+    }
+  }
+
+  #[test]
+  fn test_check() {
+    let api = MockApi::default();
+    assert_eq!(AssetInfoUnchecked::Native("uosmo".to_string()).check(&api).unwrap(), AssetInfo::Native("uosmo".to_string()));
+    assert!(AssetInfoUnchecked::Native("u$osmo".to_string()).check(&api).is_err());
+    assert_eq!(
+      AssetInfoUnchecked::Cw20("token".to_string()).check(&api).unwrap(),
+      AssetInfo::Cw20(Addr::unchecked("token")),
+    );
+    assert!(AssetInfoUnchecked::Cw20("Token".to_string()).check(&api).is_err());
+
+    // checked and unchecked infos share the same representation
+    let info: AssetInfoUnchecked = from_json(br#"{"cw20":"token"}"#).unwrap();
+    assert_eq!(info, AssetInfo::Cw20(Addr::unchecked("token")).into());
+  }
+
+  #[test]
+  fn test_cw20_queries() {
+    let mut deps = mock_dependencies();
+    deps.querier.update_wasm(|query| {
+      let WasmQuery::Smart { contract_addr, msg } = query else { panic!("unexpected query") };
+      if contract_addr != "token" {
+        return SystemResult::Err(SystemError::NoSuchContract { addr: contract_addr.clone() });
+      }
+      let res = match from_json(msg).unwrap() {
+        Cw20QueryMsg::TokenInfo {} => to_json_binary(&TokenInfoResponse {
+          name: "Token".to_string(),
+          symbol: "TKN".to_string(),
+          decimals: 6,
+          total_supply: Uint128::new(1000),
+        }),
+        Cw20QueryMsg::Balance { .. } => to_json_binary(&BalanceResponse { balance: Uint128::new(100) }),
+        _ => panic!("unexpected query"),
+      };
+      SystemResult::Ok(ContractResult::Ok(res.unwrap()))
+    });
+    let querier = QuerierWrapper::<Empty>::new(&deps.querier);
+
+    let token = AssetInfo::Cw20(Addr::unchecked("token"));
+    token.assert_exists(&querier).unwrap();
+    assert_eq!(token.query_balance(&querier, "user").unwrap(), Uint128::new(100));
+    assert_eq!(token.query_decimals(&querier).unwrap(), 6);
+
+    let missing = AssetInfo::Cw20(Addr::unchecked("missing"));
+    assert!(missing.assert_exists(&querier).is_err());
+    assert!(missing.query_balance(&querier, "user").is_err());
+    assert!(missing.query_decimals(&querier).is_err());
+  }
+
+  #[test]
+  fn test_native_balance() {
+    let deps = mock_dependencies_with_balance(&coins(100, "uosmo"));
+    let querier = QuerierWrapper::<Empty>::new(&deps.querier);
+    let info = AssetInfo::Native("uosmo".to_string());
+    info.assert_exists(&querier).unwrap();
+    assert_eq!(info.query_balance(&querier, "cosmos2contract").unwrap(), Uint128::new(100));
+  }
+
+  #[test]
+  fn test_transfer_msg() {
+    assert_eq!(Asset::native("uosmo", 100u128).transfer_msg("user").unwrap(), CosmosMsg::Bank(BankMsg::Send {
+      to_address: "user".to_string(),
+      amount: coins(100, "uosmo"),
+    }));
+    assert_eq!(Asset::cw20(Addr::unchecked("token"), 100u128).transfer_msg("user").unwrap(), CosmosMsg::Wasm(WasmMsg::Execute {
+      contract_addr: "token".to_string(),
+      msg: to_json_binary(&Cw20ExecuteMsg::Transfer { recipient: "user".to_string(), amount: Uint128::new(100) }).unwrap(),
+      funds: vec![],
+    }));
+  }
+
+  #[test]
+  fn test_asset_list() {
+    let token = Addr::unchecked("token");
+    let mut list = AssetList::try_from(vec![
+      Asset::native("uosmo", 100u128),
+      Asset::cw20(token.clone(), 50u128),
+      Asset::native("uosmo", 20u128),
+    ]).unwrap();
+    assert_eq!(list.len(), 2);
+    assert_eq!(list.amount(&AssetInfo::Native("uosmo".to_string())), Uint128::new(120));
+
+    list.deduct(&Asset::cw20(token.clone(), 50u128)).unwrap();
+    assert_eq!(list.len(), 1);
+    assert!(list.deduct(&Asset::native("uosmo", 121u128)).is_err());
+    assert!(list.deduct(&Asset::cw20(token, 1u128)).is_err());
+
+    assert!(AssetList::try_from(vec![Asset::native("uosmo", u128::MAX), Asset::native("uosmo", 1u128)]).is_err());
+
+    list.add(&Asset::native("uatom", 0u128)).unwrap();
+    assert_eq!(list.transfer_msgs("user").unwrap(), vec![CosmosMsg::Bank(BankMsg::Send {
+      to_address: "user".to_string(),
+      amount: coins(120, "uosmo"),
+    })]);
+  }
+
+  #[test]
+  fn test_storage_keys() {
+    let mut deps = mock_dependencies();
+    let map: Map<(u64, &AssetInfo), u64> = Map::new("map");
+    let uosmo = AssetInfo::Native("uosmo".to_string());
+    let uatom = AssetInfo::Native("uatom".to_string());
+    let token = AssetInfo::Cw20(Addr::unchecked("token"));
+    for (i, asset) in [&uosmo, &uatom, &token].into_iter().enumerate() {
+      map.save(deps.as_mut().storage, (1, asset), &(i as u64)).unwrap();
+    }
+    map.save(deps.as_mut().storage, (2, &uosmo), &3).unwrap();
+
+    // same layout as a `(AssetKind, &str)` key
+    let raw: Map<(u64, &[u8], &str), u64> = Map::new("map");
+    assert_eq!(raw.load(deps.as_ref().storage, (1, b"cw20", "token")).unwrap(), 2);
+
+    let assets = map.prefix(1).range(deps.as_ref().storage, None, None, Order::Ascending)
+      .collect::<StdResult<Vec<_>>>().unwrap();
+    assert_eq!(assets, vec![(token.clone(), 2), (uatom.clone(), 1), (uosmo.clone(), 0)]);
+
+    let by_kind: Map<&AssetInfo, u64> = Map::new("by_kind");
+    for (i, asset) in [&uosmo, &uatom, &token].into_iter().enumerate() {
+      by_kind.save(deps.as_mut().storage, asset, &(i as u64)).unwrap();
+    }
+    let natives = by_kind.prefix(AssetKind::Native).keys(deps.as_ref().storage, None, None, Order::Ascending)
+      .collect::<StdResult<Vec<_>>>().unwrap();
+    assert_eq!(natives, vec!["uatom", "uosmo"]);
+    let cw20s = by_kind.prefix(AssetKind::Cw20).keys(deps.as_ref().storage, None, None, Order::Ascending)
+      .collect::<StdResult<Vec<_>>>().unwrap();
+    assert_eq!(cw20s, vec!["token"]);
+  }
+}
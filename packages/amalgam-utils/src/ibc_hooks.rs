@@ -0,0 +1,121 @@
+//! Types for the [ibc-hooks](https://github.com/osmosis-labs/osmosis/tree/main/x/ibc-hooks) wasm middleware.
+//!
+//! ICS20 transfers with a memo of the form
+//!
+//! ```json
+//! { "wasm": { "contract": "osmo1...", "msg": { "deposit": { "recipient": "osmo1..." } } } }
+//! ```
+//!
+//! execute `msg` on `contract` with the transferred tokens as funds, once they arrive. The sender of the execution is
+//! not the original sender on the source chain, but an intermediary address derived from the channel and the original
+//! sender, which nobody controls. Contracts must therefore pay out to an explicit recipient instead of the sender.
+//!
+//! Conversely, contracts sending ICS20 transfers with a [`CallbackMemo`] are notified of the outcome of the transfer
+//! through the [`IbcLifecycleComplete`] sudo message. `IbcMsg::Transfer` cannot carry a memo, so such transfers must
+//! be sent as [`MsgTransfer`] instead, whose response contains the sequence of the packet to match the callback to.
+use amalgam_macros::typeurl;
+use bech32::{FromBase32, ToBase32, Variant};
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{Addr, QuerierWrapper, StdError, StdResult};
+use prost::Message;
+use sha2::{Digest, Sha256};
+
+use crate::tokenfactory::Coin;
+
+const SENDER_PREFIX: &str = "ibc-wasm-hook-intermediary";
+
+#[cw_serde]
+pub struct Memo<T> {
+  pub wasm: WasmHook<T>,
+}
+
+#[cw_serde]
+pub struct WasmHook<T> {
+  /// Contract to execute, which must also be the receiver of the transfer.
+  pub contract: String,
+  pub msg: T,
+}
+
+/// Derive the intermediary sender of hook executions for transfers of `original_sender` arriving through the local
+/// `channel`, encoded with the local chain's `bech32_prefix`.
+pub fn intermediate_sender(channel: &str, original_sender: &str, bech32_prefix: &str) -> StdResult<String> {
+  let type_hash = Sha256::digest(SENDER_PREFIX.as_bytes());
+  let hash = Sha256::new()
+    .chain_update(type_hash)
+    .chain_update(format!("{}/{}", channel, original_sender).as_bytes())
+    .finalize();
+  bech32::encode(bech32_prefix, hash.to_base32(), Variant::Bech32)
+    .map_err(|err| StdError::generic_err(format!("Invalid bech32 prefix: {}", err)))
+}
+
+/// Whether `sender` may be the intermediary sender of a hook execution, i.e. a 32 byte bech32 address that is not a
+/// contract. Intermediary addresses cannot be told apart from other such accounts, like interchain accounts, which are
+/// then treated the same way.
+pub fn is_intermediate_sender(querier: &QuerierWrapper, sender: &Addr) -> bool {
+  let is_hash = bech32::decode(sender.as_str())
+    .ok()
+    .and_then(|(_, data, _)| Vec::<u8>::from_base32(&data).ok())
+    .is_some_and(|data| data.len() == 32);
+  is_hash && querier.query_wasm_contract_info(sender).is_err()
+}
+
+/// Memo requesting an [`IbcLifecycleComplete`] callback to `ibc_callback` for an outgoing transfer.
+#[cw_serde]
+pub struct CallbackMemo {
+  pub ibc_callback: String,
+}
+
+/// Callback of ibc-hooks once an outgoing transfer was acknowledged or timed out, received as `sudo` message
+/// `{ "ibc_lifecycle_complete": ... }`.
+#[cw_serde]
+pub enum IbcLifecycleComplete {
+  IbcAck {
+    /// Source channel of the packet.
+    channel: String,
+    sequence: u64,
+    /// JSON encoded acknowledgement.
+    ack: String,
+    success: bool,
+  },
+  IbcTimeout {
+    /// Source channel of the packet.
+    channel: String,
+    sequence: u64,
+  },
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Height {
+  #[prost(uint64, tag = "1")]
+  pub revision_number: u64,
+  #[prost(uint64, tag = "2")]
+  pub revision_height: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+#[typeurl("/ibc.applications.transfer.v1.MsgTransfer")]
+pub struct MsgTransfer {
+  #[prost(string, tag = "1")]
+  pub source_port: String,
+  #[prost(string, tag = "2")]
+  pub source_channel: String,
+  #[prost(message, optional, tag = "3")]
+  pub token: Option<Coin>,
+  #[prost(string, tag = "4")]
+  pub sender: String,
+  #[prost(string, tag = "5")]
+  pub receiver: String,
+  #[prost(message, optional, tag = "6")]
+  pub timeout_height: Option<Height>,
+  /// Timeout in nanoseconds since the unix epoch.
+  #[prost(uint64, tag = "7")]
+  pub timeout_timestamp: u64,
+  #[prost(string, tag = "8")]
+  pub memo: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct MsgTransferResponse {
+  #[prost(uint64, tag = "1")]
+  pub sequence: u64,
+}
@@ -1 +1,9 @@
+// lets code generated by `amalgam_macros` refer to this crate by name from within it
+extern crate self as amalgam_utils;
+
+pub mod asset;
+pub mod ibc_hooks;
+pub mod pool;
+pub mod pricing;
+pub mod stargate;
 pub mod tokenfactory;
@@ -0,0 +1,131 @@
+use cosmwasm_std::{QuerierWrapper, Uint128};
+
+/// Reserves of a liquidity pool along with the total amount of LP shares issued against them.
+pub struct PoolReserves {
+  /// Pairs of (asset, amount), where the asset is the native denom or cw20 contract address.
+  pub reserves: Vec<(String, Uint128)>,
+  pub total_shares: Uint128,
+}
+
+impl PoolReserves {
+  /// Reserve of `asset` in the pool, or `None` if the pool does not contain `asset`.
+  pub fn reserve(&self, asset: &str) -> Option<Uint128> {
+    self.reserves.iter()
+      .find(|(reserve, _)| reserve == asset)
+      .map(|(_, amount)| *amount)
+  }
+}
+
+pub mod astroport {
+  use super::*;
+
+  use cosmwasm_schema::cw_serde;
+  use cosmwasm_std::{Addr, StdResult};
+  use schemars::JsonSchema;
+  use serde::{Deserialize, Serialize};
+
+  #[cw_serde]
+  pub enum QueryMsg {
+    Pair {},
+    Pool {},
+  }
+
+  /// Part of the response to [`QueryMsg::Pair`]. Other fields differ between pair implementations and are ignored.
+  #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+  pub struct PairInfo {
+    /// cw20 contract address or native denom of the LP shares.
+    pub liquidity_token: String,
+  }
+
+  #[cw_serde]
+  pub struct PoolResponse {
+    pub assets: Vec<Asset>,
+    pub total_share: Uint128,
+  }
+
+  #[cw_serde]
+  pub struct Asset {
+    pub info: AssetInfo,
+    pub amount: Uint128,
+  }
+
+  #[cw_serde]
+  pub enum AssetInfo {
+    Token { contract_addr: Addr },
+    NativeToken { denom: String },
+  }
+
+  /// Query the LP token of an Astroport-style pair contract.
+  pub fn query_liquidity_token(querier: &QuerierWrapper, pair: impl Into<String>) -> StdResult<String> {
+    let res: PairInfo = querier.query_wasm_smart(pair, &QueryMsg::Pair {})?;
+    Ok(res.liquidity_token)
+  }
+
+  /// Query the reserves of an Astroport-style pair contract.
+  pub fn query_reserves(querier: &QuerierWrapper, pair: impl Into<String>) -> StdResult<PoolReserves> {
+    let res: PoolResponse = querier.query_wasm_smart(pair, &QueryMsg::Pool {})?;
+    Ok(PoolReserves {
+      reserves: res.assets.into_iter().map(|asset| {
+        let key = match asset.info {
+          AssetInfo::Token { contract_addr } => contract_addr.to_string(),
+          AssetInfo::NativeToken { denom } => denom,
+        };
+        (key, asset.amount)
+      }).collect(),
+      total_shares: res.total_share,
+    })
+  }
+}
+
+pub mod osmosis {
+  use super::*;
+
+  use amalgam_macros::stargate_query;
+  use cosmwasm_std::StdResult;
+  use prost::Message;
+
+  use crate::stargate::parse_amount;
+  use crate::tokenfactory::Coin;
+
+  #[derive(Clone, PartialEq, Message)]
+  #[stargate_query(path = "/osmosis.gamm.v1beta1.Query/TotalPoolLiquidity", response = QueryTotalPoolLiquidityResponse)]
+  pub struct QueryTotalPoolLiquidityRequest {
+    #[prost(uint64, tag = "1")]
+    pub pool_id: u64,
+  }
+
+  #[derive(Clone, PartialEq, Message)]
+  pub struct QueryTotalPoolLiquidityResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub liquidity: Vec<Coin>,
+  }
+
+  #[derive(Clone, PartialEq, Message)]
+  #[stargate_query(path = "/osmosis.gamm.v1beta1.Query/TotalShares", response = QueryTotalSharesResponse)]
+  pub struct QueryTotalSharesRequest {
+    #[prost(uint64, tag = "1")]
+    pub pool_id: u64,
+  }
+
+  #[derive(Clone, PartialEq, Message)]
+  pub struct QueryTotalSharesResponse {
+    #[prost(message, optional, tag = "1")]
+    pub total_shares: Option<Coin>,
+  }
+
+  /// Query the reserves of an Osmosis GAMM pool.
+  pub fn query_reserves(querier: &QuerierWrapper, pool_id: u64) -> StdResult<PoolReserves> {
+    let liquidity = QueryTotalPoolLiquidityRequest { pool_id }.query(querier)?;
+    let shares = QueryTotalSharesRequest { pool_id }.query(querier)?;
+
+    Ok(PoolReserves {
+      reserves: liquidity.liquidity.into_iter()
+        .map(|coin| Ok((coin.denom, parse_amount(&coin.amount)?)))
+        .collect::<StdResult<_>>()?,
+      total_shares: shares.total_shares
+        .map(|coin| parse_amount(&coin.amount))
+        .transpose()?
+        .unwrap_or_default(),
+    })
+  }
+}
@@ -0,0 +1,40 @@
+//! Query interface for external pricing contracts.
+//!
+//! A pricing contract provides the conversion rate of a component into Amalgam. It must answer
+//! [`PricingQueryMsg::Rate`] for every component it is registered for:
+//!
+//! ```json
+//! { "rate": { "token": "uosmo" } }
+//! ```
+//!
+//! with a [`RateResponse`]:
+//!
+//! ```json
+//! { "rate": "1.5", "updated_at": "1700000000000000000" }
+//! ```
+use cosmwasm_schema::{cw_serde, QueryResponses};
+use cosmwasm_std::{Decimal, QuerierWrapper, StdResult, Timestamp};
+
+#[cw_serde]
+#[derive(QueryResponses)]
+pub enum PricingQueryMsg {
+  /// Amalgam per unit of the given token.
+  #[returns(RateResponse)]
+  Rate {
+    /// Native denom or cw20 contract address of the component.
+    token: String,
+  },
+}
+
+#[cw_serde]
+pub struct RateResponse {
+  /// Amalgam per unit of the token.
+  pub rate: Decimal,
+  /// Time the rate was last updated, used for staleness checks.
+  pub updated_at: Timestamp,
+}
+
+/// Query the rate of `token` from the pricing contract at `addr`.
+pub fn query_rate(querier: &QuerierWrapper, addr: impl Into<String>, token: impl Into<String>) -> StdResult<RateResponse> {
+  querier.query_wasm_smart(addr, &PricingQueryMsg::Rate { token: token.into() })
+}
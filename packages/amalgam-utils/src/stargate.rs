@@ -0,0 +1,51 @@
+use cosmwasm_std::{to_json_vec, ContractResult, CustomQuery, Empty, QuerierWrapper, QueryRequest, StdError, StdResult, SystemResult, Uint128};
+use prost::Message;
+
+/// Protobuf message along with its type url, as in `google.protobuf.Any`.
+#[derive(Clone, PartialEq, Message)]
+pub struct Any {
+  #[prost(string, tag = "1")]
+  pub type_url: String,
+  #[prost(bytes = "vec", tag = "2")]
+  pub value: Vec<u8>,
+}
+
+/// Protobuf request of a Stargate query, tied to the path it is sent to and the type of its response. Usually
+/// implemented with `#[stargate_query]`.
+pub trait StargateQuery: Message {
+  /// gRPC path of the query, e.g. "/osmosis.tokenfactory.v1beta1.Query/Params"
+  const PATH: &'static str;
+
+  type Response: Message + Default;
+}
+
+/// Send the Stargate query `request` and decode its response.
+pub fn query<C: CustomQuery, Q: StargateQuery>(querier: &QuerierWrapper<C>, request: Q) -> StdResult<Q::Response> {
+  query_proto(querier, Q::PATH, request)
+}
+
+/// Decode a protobuf message, e.g. the data of a submessage reply.
+pub fn decode<T: Message + Default>(data: &[u8]) -> StdResult<T> {
+  T::decode(data).map_err(|err| StdError::parse_err(std::any::type_name::<T>(), err))
+}
+
+/// Send a Stargate query with the protobuf encoded `data` to `path` and decode the response.
+fn query_proto<C: CustomQuery, T: Message + Default>(querier: &QuerierWrapper<C>, path: &str, data: impl Message) -> StdResult<T> {
+  let request: QueryRequest<Empty> = QueryRequest::Stargate {
+    path: path.to_string(),
+    data: data.encode_to_vec().into(),
+  };
+  let raw = to_json_vec(&request)?;
+  match querier.raw_query(&raw) {
+    SystemResult::Err(err) => Err(StdError::generic_err(format!("Querier system error: {}", err))),
+    SystemResult::Ok(ContractResult::Err(err)) => Err(StdError::generic_err(format!("Querier contract error: {}", err))),
+    SystemResult::Ok(ContractResult::Ok(value)) => decode(value.as_slice()),
+  }
+}
+
+/// Parse an amount of a protobuf `Coin`, which is encoded as a string.
+pub(crate) fn parse_amount(amount: &str) -> StdResult<Uint128> {
+  amount.parse::<u128>()
+    .map(Uint128::new)
+    .map_err(|err| StdError::parse_err("Uint128", err))
+}
@@ -1,7 +1,7 @@
 use prost::Message;
-use cosmwasm_std::{Addr, CosmosMsg, Uint128};
+use cosmwasm_std::{Addr, CosmosMsg, QuerierWrapper, StdError, StdResult, Uint128};
 
-use amalgam_macros::typeurl;
+use amalgam_macros::{stargate_query, typeurl};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
@@ -26,6 +26,15 @@ pub trait TFToken {
 
   /// Burn tokens from a recipient
   fn burn(&self, amount: Uint128, sender: String) -> Vec<CosmosMsg>;
+
+  /// Hand the admin rights over the token to `new_admin`
+  fn change_admin(&self, new_admin: String) -> Vec<CosmosMsg>;
+
+  /// Transfer tokens between two addresses without their consent
+  fn force_transfer(&self, amount: Uint128, from: String, to: String) -> Vec<CosmosMsg>;
+
+  /// Register a contract to be called before every transfer of the token, or unregister it with an empty address
+  fn set_before_send_hook(&self, contract: String) -> Vec<CosmosMsg>;
 }
 
 #[derive(Clone, PartialEq, Message)]
@@ -58,6 +67,16 @@ pub struct DenomMetadata {
   pub uri_hash: String,
 }
 
+impl DenomMetadata {
+  /// Exponent of the `display` denom unit, i.e. the number of decimals the token is shown with.
+  pub fn display_exponent(&self) -> StdResult<u32> {
+    self.denom_units.iter()
+      .find(|unit| unit.denom == self.display || unit.aliases.contains(&self.display))
+      .map(|unit| unit.exponent)
+      .ok_or_else(|| StdError::not_found(format!("display unit of {}", self.base)))
+  }
+}
+
 #[derive(Clone, PartialEq, Message, Serialize, Deserialize, JsonSchema)]
 pub struct DenomUnit {
   #[prost(string, tag = "1")]
@@ -68,9 +87,42 @@ pub struct DenomUnit {
   pub aliases: Vec<String>,
 }
 
+#[derive(Clone, PartialEq, Message)]
+#[stargate_query(path = "/cosmos.bank.v1beta1.Query/DenomMetadata", response = QueryDenomMetadataResponse)]
+pub struct QueryDenomMetadataRequest {
+  #[prost(string, tag = "1")]
+  pub denom: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct QueryDenomMetadataResponse {
+  #[prost(message, optional, tag = "1")]
+  pub metadata: Option<DenomMetadata>,
+}
+
+/// Query the bank metadata of `denom`.
+pub fn query_denom_metadata(querier: &QuerierWrapper, denom: String) -> StdResult<DenomMetadata> {
+  QueryDenomMetadataRequest { denom: denom.clone() }.query(querier)?
+    .metadata
+    .ok_or_else(|| StdError::not_found(format!("metadata of {}", denom)))
+}
+
+/// Deduct the denom creation `fee` from the attached `funds`, returning the excess, or `None` if the funds do not cover
+/// the fee.
+pub fn deduct_fee(mut funds: Vec<cosmwasm_std::Coin>, fee: &[cosmwasm_std::Coin]) -> Option<Vec<cosmwasm_std::Coin>> {
+  for required in fee {
+    let fund = funds.iter_mut().find(|fund| fund.denom == required.denom)?;
+    fund.amount = fund.amount.checked_sub(required.amount).ok()?;
+  }
+  funds.retain(|fund| !fund.amount.is_zero());
+  Some(funds)
+}
+
 pub mod osmosis {
   use super::*;
 
+  use crate::stargate::parse_amount;
+
   #[derive(Clone, PartialEq, Message)]
   #[typeurl("/osmosis.tokenfactory.v1beta1.MsgCreateDenom")]
   pub struct MsgCreateDenom {
@@ -80,6 +132,12 @@ pub mod osmosis {
     pub subdenom: String,
   }
 
+  #[derive(Clone, PartialEq, Message)]
+  pub struct MsgCreateDenomResponse {
+    #[prost(string, tag = "1")]
+    pub new_token_denom: String,
+  }
+
   #[derive(Clone, PartialEq, Message)]
   #[typeurl("/osmosis.tokenfactory.v1beta1.MsgSetDenomMetadata")]
   pub struct MsgSetDenomMetadata {
@@ -111,14 +169,135 @@ pub mod osmosis {
     pub burn_from_address: String,
   }
 
+  #[derive(Clone, PartialEq, Message)]
+  #[typeurl("/osmosis.tokenfactory.v1beta1.MsgChangeAdmin")]
+  pub struct MsgChangeAdmin {
+    #[prost(string, tag = "1")]
+    pub sender: String,
+    #[prost(string, tag = "2")]
+    pub denom: String,
+    #[prost(string, tag = "3")]
+    pub new_admin: String,
+  }
+
+  #[derive(Clone, PartialEq, Message)]
+  #[typeurl("/osmosis.tokenfactory.v1beta1.MsgForceTransfer")]
+  pub struct MsgForceTransfer {
+    #[prost(string, tag = "1")]
+    pub sender: String,
+    #[prost(message, required, tag = "2")]
+    pub amount: Coin,
+    #[prost(string, tag = "3")]
+    pub transfer_from_address: String,
+    #[prost(string, tag = "4")]
+    pub transfer_to_address: String,
+  }
+
+  #[derive(Clone, PartialEq, Message)]
+  #[typeurl("/osmosis.tokenfactory.v1beta1.MsgSetBeforeSendHook")]
+  pub struct MsgSetBeforeSendHook {
+    #[prost(string, tag = "1")]
+    pub sender: String,
+    #[prost(string, tag = "2")]
+    pub denom: String,
+    #[prost(string, tag = "3")]
+    pub cosmwasm_address: String,
+  }
+
+  #[derive(Clone, PartialEq, Message)]
+  #[stargate_query(path = "/osmosis.tokenfactory.v1beta1.Query/Params", response = QueryParamsResponse)]
+  pub struct QueryParamsRequest {}
+
+  #[derive(Clone, PartialEq, Message)]
+  pub struct QueryParamsResponse {
+    #[prost(message, optional, tag = "1")]
+    pub params: Option<Params>,
+  }
+
+  #[derive(Clone, PartialEq, Message)]
+  pub struct Params {
+    #[prost(message, repeated, tag = "1")]
+    pub denom_creation_fee: Vec<Coin>,
+    #[prost(uint64, tag = "2")]
+    pub denom_creation_gas_consume: u64,
+  }
+
+  impl Params {
+    /// Fee charged to the creator of a new denom.
+    pub fn creation_fee(&self) -> StdResult<Vec<cosmwasm_std::Coin>> {
+      self.denom_creation_fee.iter()
+        .map(|coin| Ok(cosmwasm_std::Coin { denom: coin.denom.clone(), amount: parse_amount(&coin.amount)? }))
+        .collect()
+    }
+  }
+
+  #[derive(Clone, PartialEq, Message)]
+  #[stargate_query(path = "/osmosis.tokenfactory.v1beta1.Query/DenomAuthorityMetadata", response = QueryDenomAuthorityMetadataResponse)]
+  pub struct QueryDenomAuthorityMetadataRequest {
+    #[prost(string, tag = "1")]
+    pub denom: String,
+  }
+
+  #[derive(Clone, PartialEq, Message)]
+  pub struct QueryDenomAuthorityMetadataResponse {
+    #[prost(message, optional, tag = "1")]
+    pub authority_metadata: Option<DenomAuthorityMetadata>,
+  }
+
+  #[derive(Clone, PartialEq, Message)]
+  pub struct DenomAuthorityMetadata {
+    /// Address allowed to mint, burn and change the metadata of the denom. Empty if the denom has no admin.
+    #[prost(string, tag = "1")]
+    pub admin: String,
+  }
+
+  #[derive(Clone, PartialEq, Message)]
+  #[stargate_query(path = "/osmosis.tokenfactory.v1beta1.Query/DenomsFromCreator", response = QueryDenomsFromCreatorResponse)]
+  pub struct QueryDenomsFromCreatorRequest {
+    #[prost(string, tag = "1")]
+    pub creator: String,
+  }
+
+  #[derive(Clone, PartialEq, Message)]
+  pub struct QueryDenomsFromCreatorResponse {
+    #[prost(string, repeated, tag = "1")]
+    pub denoms: Vec<String>,
+  }
+
+  /// Query the parameters of the tokenfactory module.
+  pub fn query_params(querier: &QuerierWrapper) -> StdResult<Params> {
+    let res = QueryParamsRequest {}.query(querier)?;
+    Ok(res.params.unwrap_or_default())
+  }
+
+  /// Query the admin of a tokenfactory `denom`, or `None` if the denom has no admin.
+  pub fn query_denom_admin(querier: &QuerierWrapper, denom: String) -> StdResult<Option<String>> {
+    let res = QueryDenomAuthorityMetadataRequest { denom }.query(querier)?;
+    Ok(res.authority_metadata.map(|metadata| metadata.admin).filter(|admin| !admin.is_empty()))
+  }
+
+  /// Query all denoms created by `creator`.
+  pub fn query_denoms_from_creator(querier: &QuerierWrapper, creator: String) -> StdResult<Vec<String>> {
+    Ok(QueryDenomsFromCreatorRequest { creator }.query(querier)?.denoms)
+  }
+
   pub struct TFToken {
     pub owner: Addr,
     pub subdenom: String,
+    pub denom: String,
   }
 
   impl TFToken {
+    /// Token of `owner` with the denom tokenfactory is expected to assign to `subdenom`.
     pub fn new(owner: Addr, subdenom: String) -> Self {
-      Self { owner, subdenom }
+      let denom = format!("factory/{}/{}", owner, subdenom);
+      Self { owner, subdenom, denom }
+    }
+
+    /// Token of `owner` with a known full `denom` of the form `factory/{creator}/{subdenom}`.
+    pub fn from_denom(owner: Addr, denom: String) -> Self {
+      let subdenom = denom.splitn(3, '/').nth(2).unwrap_or_default().to_string();
+      Self { owner, subdenom, denom }
     }
   }
 
@@ -132,7 +311,7 @@ pub mod osmosis {
     }
 
     fn denom(&self) -> String {
-      format!("factory/{}/{}", self.owner, self.subdenom)
+      self.denom.clone()
     }
 
     fn create(&self) -> Vec<CosmosMsg> {
@@ -161,5 +340,174 @@ pub mod osmosis {
         burn_from_address: sender,
       }.into()]
     }
+
+    fn change_admin(&self, new_admin: String) -> Vec<CosmosMsg> {
+      vec![MsgChangeAdmin {
+        sender: self.owner.to_string(),
+        denom: self.denom(),
+        new_admin,
+      }.into()]
+    }
+
+    fn force_transfer(&self, amount: Uint128, from: String, to: String) -> Vec<CosmosMsg> {
+      vec![MsgForceTransfer {
+        sender: self.owner.to_string(),
+        amount: Coin { denom: self.denom(), amount: amount.to_string() },
+        transfer_from_address: from,
+        transfer_to_address: to,
+      }.into()]
+    }
+
+    fn set_before_send_hook(&self, contract: String) -> Vec<CosmosMsg> {
+      vec![MsgSetBeforeSendHook {
+        sender: self.owner.to_string(),
+        denom: self.denom(),
+        cosmwasm_address: contract,
+      }.into()]
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::osmosis::*;
+  use super::*;
+
+  use cosmwasm_std::{
+    from_json, ContractResult, Empty, Querier, QuerierResult, QuerierWrapper, QueryRequest, SystemError,
+    SystemResult,
+  };
+
+  use crate::stargate;
+
+  /// Answers Stargate queries to `path` with `response`, if the request matches `request`.
+  struct GoldenQuerier {
+    path: &'static str,
+    request: Vec<u8>,
+    response: Vec<u8>,
+  }
+
+  impl Querier for GoldenQuerier {
+    fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+      match from_json::<QueryRequest<Empty>>(bin_request) {
+        Ok(QueryRequest::Stargate { path, data }) if path == self.path && data.as_slice() == self.request => {
+          SystemResult::Ok(ContractResult::Ok(self.response.clone().into()))
+        }
+        _ => SystemResult::Err(SystemError::UnsupportedRequest { kind: "golden".to_string() }),
+      }
+    }
+  }
+
+  /// Assert that `msg` encodes to and decodes from `golden`.
+  fn assert_golden<T: Message + Default + PartialEq + std::fmt::Debug>(msg: T, golden: &[u8]) {
+    assert_eq!(msg.encode_to_vec(), golden);
+    assert_eq!(stargate::decode::<T>(golden).unwrap(), msg);
+  }
+
+  fn osmo_coin() -> Coin {
+    Coin { denom: "uosmo".to_string(), amount: "100".to_string() }
+  }
+
+  const COIN: &[u8] = b"\x0a\x05uosmo\x12\x03100";
+
+  #[test]
+  fn test_bank_golden() {
+    assert_golden(osmo_coin(), COIN);
+    assert_golden(DenomUnit { denom: "LP".to_string(), exponent: 6, aliases: vec!["lp".to_string()] },
+      b"\x0a\x02LP\x10\x06\x1a\x02lp");
+  }
+
+  #[test]
+  fn test_display_exponent() {
+    let unit = |denom: &str, exponent, aliases: &[&str]| DenomUnit {
+      denom: denom.to_string(),
+      exponent,
+      aliases: aliases.iter().map(|alias| alias.to_string()).collect(),
+    };
+    let mut metadata = DenomMetadata {
+      base: "uosmo".to_string(),
+      display: "osmo".to_string(),
+      denom_units: vec![unit("uosmo", 0, &[]), unit("mosmo", 3, &[]), unit("OSMO", 6, &["osmo"])],
+      ..Default::default()
+    };
+    assert_eq!(metadata.display_exponent().unwrap(), 6);
+    metadata.display = "mosmo".to_string();
+    assert_eq!(metadata.display_exponent().unwrap(), 3);
+    metadata.display = "nosmo".to_string();
+    assert!(metadata.display_exponent().is_err());
+  }
+
+  #[test]
+  fn test_create_denom_response_golden() {
+    assert_golden(MsgCreateDenomResponse { new_token_denom: "factory/osmo1abc/lp".to_string() },
+      b"\x0a\x13factory/osmo1abc/lp");
+  }
+
+  #[test]
+  fn test_params_golden() {
+    let mut golden = b"\x0a\x12\x0a\x0c".to_vec();
+    golden.extend_from_slice(COIN);
+    golden.extend_from_slice(b"\x10\x80\x89\x7a");
+    let res = QueryParamsResponse {
+      params: Some(Params { denom_creation_fee: vec![osmo_coin()], denom_creation_gas_consume: 2_000_000 }),
+    };
+    assert_golden(res.clone(), &golden);
+    assert_eq!(res.params.unwrap().creation_fee().unwrap(), cosmwasm_std::coins(100, "uosmo"));
+
+    let querier = GoldenQuerier { path: "/osmosis.tokenfactory.v1beta1.Query/Params", request: vec![], response: golden };
+    let params = query_params(&QuerierWrapper::<Empty>::new(&querier)).unwrap();
+    assert_eq!(params.denom_creation_gas_consume, 2_000_000);
+  }
+
+  #[test]
+  fn test_denom_authority_metadata_golden() {
+    assert_golden(QueryDenomAuthorityMetadataRequest { denom: "factory/a/x".to_string() }, b"\x0a\x0bfactory/a/x");
+    let golden = b"\x0a\x0a\x0a\x08osmo1abc";
+    assert_golden(QueryDenomAuthorityMetadataResponse {
+      authority_metadata: Some(DenomAuthorityMetadata { admin: "osmo1abc".to_string() }),
+    }, golden);
+
+    let querier = GoldenQuerier {
+      path: "/osmosis.tokenfactory.v1beta1.Query/DenomAuthorityMetadata",
+      request: b"\x0a\x0bfactory/a/x".to_vec(),
+      response: golden.to_vec(),
+    };
+    let querier = QuerierWrapper::<Empty>::new(&querier);
+    assert_eq!(query_denom_admin(&querier, "factory/a/x".to_string()).unwrap().as_deref(), Some("osmo1abc"));
+    assert!(query_denom_admin(&querier, "factory/a/y".to_string()).is_err());
+
+    // a denom whose admin was renounced reports an empty admin
+    let querier = GoldenQuerier {
+      path: "/osmosis.tokenfactory.v1beta1.Query/DenomAuthorityMetadata",
+      request: b"\x0a\x0bfactory/a/x".to_vec(),
+      response: b"\x0a\x00".to_vec(),
+    };
+    assert_eq!(query_denom_admin(&QuerierWrapper::<Empty>::new(&querier), "factory/a/x".to_string()).unwrap(), None);
+  }
+
+  #[test]
+  fn test_denoms_from_creator_golden() {
+    assert_golden(QueryDenomsFromCreatorRequest { creator: "osmo1abc".to_string() }, b"\x0a\x08osmo1abc");
+    let golden = b"\x0a\x0bfactory/a/x\x0a\x0bfactory/a/y";
+    assert_golden(QueryDenomsFromCreatorResponse {
+      denoms: vec!["factory/a/x".to_string(), "factory/a/y".to_string()],
+    }, golden);
+
+    let querier = GoldenQuerier {
+      path: "/osmosis.tokenfactory.v1beta1.Query/DenomsFromCreator",
+      request: b"\x0a\x08osmo1abc".to_vec(),
+      response: golden.to_vec(),
+    };
+    let denoms = QueryDenomsFromCreatorRequest { creator: "osmo1abc".to_string() }
+      .query(&QuerierWrapper::<Empty>::new(&querier))
+      .unwrap()
+      .denoms;
+    assert_eq!(denoms, vec!["factory/a/x", "factory/a/y"]);
+  }
+
+  #[test]
+  fn test_decode_invalid() {
+    let err = stargate::decode::<MsgCreateDenomResponse>(b"\x0a\x13factory").unwrap_err();
+    assert!(err.to_string().contains("MsgCreateDenomResponse"));
   }
 }
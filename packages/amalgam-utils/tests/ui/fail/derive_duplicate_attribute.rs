@@ -0,0 +1,8 @@
+use amalgam_macros::TypeUrl;
+
+#[derive(TypeUrl)]
+#[type_url("/cosmos.bank.v1beta1.Coin")]
+#[type_url("/cosmos.bank.v1beta1.Metadata")]
+pub struct Coin {}
+
+fn main() {}
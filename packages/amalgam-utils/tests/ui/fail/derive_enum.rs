@@ -0,0 +1,9 @@
+use amalgam_macros::TypeUrl;
+
+#[derive(TypeUrl)]
+#[type_url("/cosmos.bank.v1beta1.Coin")]
+pub enum Coin {
+  Native,
+}
+
+fn main() {}
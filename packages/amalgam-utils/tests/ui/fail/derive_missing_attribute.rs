@@ -0,0 +1,6 @@
+use amalgam_macros::TypeUrl;
+
+#[derive(TypeUrl)]
+pub struct Coin {}
+
+fn main() {}
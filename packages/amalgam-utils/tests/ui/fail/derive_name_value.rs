@@ -0,0 +1,7 @@
+use amalgam_macros::TypeUrl;
+
+#[derive(TypeUrl)]
+#[type_url = "/cosmos.bank.v1beta1.Coin"]
+pub struct Coin {}
+
+fn main() {}
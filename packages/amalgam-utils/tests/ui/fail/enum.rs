@@ -0,0 +1,8 @@
+use amalgam_macros::typeurl;
+
+#[typeurl("/cosmos.bank.v1beta1.Coin")]
+pub enum Coin {
+  Native,
+}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use amalgam_macros::typeurl;
+
+#[typeurl]
+pub struct Coin {}
+
+fn main() {}
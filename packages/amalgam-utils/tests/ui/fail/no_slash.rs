@@ -0,0 +1,6 @@
+use amalgam_macros::typeurl;
+
+#[typeurl("cosmos.bank.v1beta1.Coin")]
+pub struct Coin {}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use amalgam_macros::stargate_query;
+
+#[stargate_query(path = "/osmosis.tokenfactory.v1beta1.Query/Params")]
+pub struct QueryParamsRequest {}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use amalgam_macros::stargate_query;
+
+#[stargate_query(path = "/osmosis.tokenfactory.v1beta1.Query/Params", response = QueryParamsResponse, method = "GET")]
+pub struct QueryParamsRequest {}
+
+pub struct QueryParamsResponse {}
+
+fn main() {}
@@ -0,0 +1,32 @@
+use amalgam_macros::typeurl;
+use cosmwasm_std::{Binary, CosmosMsg};
+use prost::Message;
+
+#[derive(Clone, PartialEq, Message)]
+#[typeurl("/cosmos.bank.v1beta1.Coin")]
+pub struct Coin {
+  #[prost(string, tag = "1")]
+  pub denom: String,
+  #[prost(string, tag = "2")]
+  pub amount: String,
+}
+
+fn main() {
+  let coin = Coin { denom: "uosmo".to_string(), amount: "100".to_string() };
+  let golden = b"\x0a\x05uosmo\x12\x03100";
+
+  let msg: CosmosMsg = coin.clone().into();
+  assert_eq!(msg, CosmosMsg::Stargate {
+    type_url: "/cosmos.bank.v1beta1.Coin".to_string(),
+    value: Binary::from(golden),
+  });
+
+  let any = coin.to_any();
+  assert_eq!(any.type_url, Coin::TYPE_URL);
+  assert_eq!(any.value, golden);
+
+  assert_eq!(Coin::try_from(Binary::from(golden)).unwrap(), coin);
+  assert_eq!(Coin::try_from(&Binary::from(golden)).unwrap(), coin);
+  let err = Coin::try_from(Binary::from(b"\x0a\x05uo")).unwrap_err();
+  assert!(err.to_string().contains("/cosmos.bank.v1beta1.Coin"));
+}
@@ -0,0 +1,33 @@
+use amalgam_macros::TypeUrl;
+use cosmwasm_std::{Binary, CosmosMsg};
+use prost::Message;
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Denom {
+  #[prost(string, tag = "1")]
+  pub denom: String,
+}
+
+#[derive(Clone, PartialEq, Message, TypeUrl)]
+#[type_url("/amalgam.test.Wrapped")]
+pub struct Wrapped<M>
+where
+  M: Message + Default + PartialEq + std::fmt::Debug,
+{
+  #[prost(message, optional, tag = "1")]
+  pub inner: Option<M>,
+}
+
+fn main() {
+  let wrapped = Wrapped { inner: Some(Denom { denom: "uosmo".to_string() }) };
+  let golden = b"\x0a\x07\x0a\x05uosmo";
+
+  let msg: CosmosMsg = wrapped.clone().into();
+  assert_eq!(msg, CosmosMsg::Stargate {
+    type_url: "/amalgam.test.Wrapped".to_string(),
+    value: Binary::from(golden),
+  });
+  assert_eq!(wrapped.to_any().value, golden);
+  assert_eq!(Wrapped::<Denom>::TYPE_URL, "/amalgam.test.Wrapped");
+  assert_eq!(Wrapped::<Denom>::try_from(Binary::from(golden)).unwrap(), wrapped);
+}
@@ -0,0 +1,39 @@
+use amalgam_macros::stargate_query;
+use amalgam_utils::stargate::StargateQuery;
+use cosmwasm_std::{
+  from_json, ContractResult, Empty, Querier, QuerierResult, QuerierWrapper, QueryRequest, SystemError, SystemResult,
+};
+use prost::Message;
+
+#[derive(Clone, PartialEq, Message)]
+#[stargate_query(path = "/osmosis.tokenfactory.v1beta1.Query/DenomsFromCreator", response = DenomsResponse)]
+pub struct DenomsRequest {
+  #[prost(string, tag = "1")]
+  pub creator: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct DenomsResponse {
+  #[prost(string, repeated, tag = "1")]
+  pub denoms: Vec<String>,
+}
+
+struct GoldenQuerier;
+
+impl Querier for GoldenQuerier {
+  fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+    match from_json::<QueryRequest<Empty>>(bin_request) {
+      Ok(QueryRequest::Stargate { path, data }) if path == DenomsRequest::PATH && data.as_slice() == b"\x0a\x01a" =>
+        SystemResult::Ok(ContractResult::Ok(b"\x0a\x0bfactory/a/x".to_vec().into())),
+      _ => SystemResult::Err(SystemError::UnsupportedRequest { kind: "golden".to_string() }),
+    }
+  }
+}
+
+fn main() {
+  let querier = GoldenQuerier;
+  let querier = QuerierWrapper::<Empty>::new(&querier);
+  let res = DenomsRequest { creator: "a".to_string() }.query(&querier).unwrap();
+  assert_eq!(res.denoms, vec!["factory/a/x"]);
+  assert!(DenomsRequest { creator: "b".to_string() }.query(&querier).is_err());
+}